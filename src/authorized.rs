@@ -0,0 +1,384 @@
+//! Authorized HTTP client.
+
+use std::fmt;
+
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::{self, IntoUrl, Method, Request, RequestBuilder, Response, StatusCode};
+
+use client::{Client, ClientError, ClientErrorKind};
+use dpop::DPOP;
+use error::{BearerChallenge, OAuth2ErrorCode};
+use manager::TokenManager;
+use provider::Provider;
+use token::{Refreshable, Token, TokenType};
+
+/// HTTP client authorizing requests with a managed access token.
+///
+/// Every request is sent with the `Authorization` header set from a valid access token, which is
+/// refreshed first if necessary. If the resource server rejects the token with a `401
+/// Unauthorized` response carrying an `invalid_token` Bearer challenge, the token is refreshed and
+/// the request is sent once more.
+///
+/// If the refreshed token is rejected in the same way, or the request cannot be replayed, the
+/// challenge is returned as `ClientErrorKind::Challenge`. Other responses, including those with
+/// challenges such as `insufficient_scope`, are returned as they are, so that their body can be
+/// read; use `BearerChallenge::from_headers` to inspect their challenge.
+///
+/// Tokens are presented as described by `Token::authorization_header`. DPoP tokens are sent along
+/// with a proof signed by the client's DPoP key, and a request rejected with a `use_dpop_nonce`
+/// challenge is sent once more if the resource server provided a new nonce. Sending a DPoP token
+/// fails with `ClientErrorKind::MissingDPoPKey` if the client has no DPoP key.
+///
+/// See [RFC 6750, section 3](http://tools.ietf.org/html/rfc6750#section-3).
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate inth_oauth2;
+/// # extern crate reqwest;
+/// # use inth_oauth2::Client;
+/// # use inth_oauth2::provider::google::Installed;
+/// use inth_oauth2::AuthorizedClient;
+///
+/// # fn main() {
+/// # let client = Client::new(Installed, String::new(), String::new(), None);
+/// # let http = reqwest::Client::new();
+/// # let token = client.request_token(&http, "").unwrap();
+/// let authorized = AuthorizedClient::new(client, http, token);
+/// let response = authorized.send(authorized.get("https://example.com/resource")).unwrap();
+/// # }
+/// ```
+pub struct AuthorizedClient<P: Provider> {
+    manager: TokenManager<P>,
+}
+
+impl<P> fmt::Debug for AuthorizedClient<P>
+where P: Provider + fmt::Debug, P::Token: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("AuthorizedClient")
+            .field("manager", &self.manager)
+            .finish()
+    }
+}
+
+impl<P> AuthorizedClient<P> where P: Provider, P::Lifetime: Refreshable, P::Token: Clone {
+    /// Creates an authorized client.
+    pub fn new(client: Client<P>, http_client: reqwest::Client, token: P::Token) -> Self {
+        AuthorizedClient::from_manager(TokenManager::new(client, http_client, token))
+    }
+
+    /// Creates an authorized client using a token manager.
+    pub fn from_manager(manager: TokenManager<P>) -> Self {
+        AuthorizedClient { manager }
+    }
+
+    /// Returns the token manager.
+    pub fn manager(&self) -> &TokenManager<P> { &self.manager }
+
+    /// Starts building a request with the underlying HTTP client.
+    pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        self.manager.http_client().request(method, url)
+    }
+
+    /// Starts building a `GET` request with the underlying HTTP client.
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    /// Starts building a `POST` request with the underlying HTTP client.
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    /// Builds and sends an authorized request.
+    pub fn send(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+        self.execute(request.build()?)
+    }
+
+    /// Sends an authorized request.
+    ///
+    /// Requests with streaming bodies cannot be replayed, so they are sent only once.
+    pub fn execute(&self, request: Request) -> Result<Response, ClientError> {
+        let mut token = self.manager.token()?;
+        let mut request = request;
+        let mut refreshed = false;
+        let mut nonce_retried = false;
+
+        loop {
+            let replay = request.try_clone();
+            let (response, nonce) = self.execute_with(request, &token)?;
+
+            let challenge = match self.challenge(&response, &token) {
+                Some(challenge) => challenge,
+                None => return Ok(response),
+            };
+
+            if challenge.code == Some(OAuth2ErrorCode::UseDPoPNonce) {
+                // Replaying is only worth it with a nonce the proof did not already carry.
+                match replay {
+                    Some(replay) if nonce && !nonce_retried => {
+                        nonce_retried = true;
+                        request = replay;
+                    },
+                    _ => return Ok(response),
+                }
+            } else if response.status() == StatusCode::UNAUTHORIZED
+                && challenge.is_invalid_token() {
+                match replay {
+                    Some(replay) if !refreshed => {
+                        refreshed = true;
+                        token = self.manager.force_refresh(token.access_token())?;
+                        request = replay;
+                    },
+                    _ => return Err(challenge.into()),
+                }
+            } else {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Sends a request authorized with a token, returning whether a new DPoP nonce was recorded.
+    fn execute_with(
+        &self,
+        mut request: Request,
+        token: &P::Token,
+    ) -> Result<(Response, bool), ClientError> {
+        let authorization = token.authorization_header()
+            .ok_or_else(|| ClientErrorKind::UnsupportedTokenType(token.token_type()))?;
+        let mut authorization = HeaderValue::from_str(&authorization)?;
+        authorization.set_sensitive(true);
+        request.headers_mut().insert(AUTHORIZATION, authorization);
+
+        let key = match token.token_type() {
+            TokenType::DPoP => Some(
+                self.manager.client().dpop.as_ref().ok_or(ClientErrorKind::MissingDPoPKey)?
+            ),
+            _ => None,
+        };
+        if let Some(key) = key {
+            let proof = key.proof(request.method(), request.url(), Some(token.access_token()));
+            request.headers_mut().insert(DPOP, HeaderValue::from_str(&proof)?);
+        }
+
+        let url = request.url().clone();
+        let response = self.manager.http_client().execute(request)?;
+        let nonce = key.is_some_and(|key| key.record_nonce(&url, response.headers()));
+        Ok((response, nonce))
+    }
+
+    /// Returns the challenge of an error response, if it carries an error code.
+    ///
+    /// See [RFC 6750, section 3.1](http://tools.ietf.org/html/rfc6750#section-3.1).
+    fn challenge(&self, response: &Response, token: &P::Token) -> Option<BearerChallenge> {
+        match response.status() {
+            StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {},
+            _ => return None,
+        }
+        BearerChallenge::from_headers_scheme(response.headers(), token.token_type().as_str())
+            .filter(|challenge| challenge.code.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{self, StatusCode};
+
+    use client::{Client, ClientError, ClientErrorKind};
+    use dpop::DPoPKey;
+    use error::BearerChallenge;
+    use test_support::{proof_claims, token_with_refresh, Request, Response, Server, TestProvider};
+    use token::{AnyToken, Bearer, DPoP, Refresh, TokenType};
+    use super::AuthorizedClient;
+
+    type Test = TestProvider<Bearer<Refresh>, Refresh>;
+    type TestDPoP = TestProvider<DPoP<Refresh>, Refresh>;
+    type TestAny = TestProvider<AnyToken<Refresh>, Refresh>;
+
+    fn authorized(server: &Server) -> AuthorizedClient<Test> {
+        let client = Client::new(Test::on(server), String::from("foo"), String::from("bar"), None);
+        let token = token_with_refresh("Bearer", "aaaaaaaa", "bbbbbbbb", 3600);
+        AuthorizedClient::new(client, reqwest::Client::new(), token)
+    }
+
+    /// Resource server accepting only the refreshed access token.
+    fn server(challenge: &'static str) -> Server {
+        Server::new(move |_, request: &Request| {
+            if request.path == "/token" {
+                return Response::json(200, r#"{"token_type":"Bearer","access_token":"cccccccc","expires_in":3600}"#);
+            }
+            if request.header("Authorization") == Some("Bearer cccccccc") {
+                Response::json(200, "{}")
+            } else {
+                Response::json(401, "{}").header("WWW-Authenticate", challenge)
+            }
+        })
+    }
+
+    fn paths(server: &Server) -> Vec<String> {
+        server.requests().into_iter().map(|request| request.path).collect()
+    }
+
+    #[test]
+    fn execute_replays_invalid_token() {
+        let server = server(r#"Bearer realm="example", error="invalid_token""#);
+        let authorized = authorized(&server);
+        let request = authorized.post(server.url("/resource")).body("foo");
+        let response = authorized.send(request).unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(vec!["/resource", "/token", "/resource"], paths(&server));
+        assert_eq!("foo", server.requests()[2].body);
+    }
+
+    #[test]
+    fn execute_without_invalid_token() {
+        let server = server(r#"Bearer realm="example""#);
+        let authorized = authorized(&server);
+        let response = authorized.send(authorized.get(server.url("/resource"))).unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+        assert_eq!(vec!["/resource"], paths(&server));
+    }
+
+    #[test]
+    fn execute_replays_once() {
+        let server = Server::new(|_, request: &Request| {
+            if request.path == "/token" {
+                return Response::json(200, r#"{"token_type":"Bearer","access_token":"cccccccc","expires_in":3600}"#);
+            }
+            Response::json(401, "{}").header("WWW-Authenticate", r#"Bearer error="invalid_token""#)
+        });
+        let authorized = authorized(&server);
+        let result = authorized.send(authorized.get(server.url("/resource")));
+        match result.map_err(ClientError::into_kind) {
+            Err(ClientErrorKind::Challenge(ref challenge)) if challenge.is_invalid_token() => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(vec!["/resource", "/token", "/resource"], paths(&server));
+    }
+
+    #[test]
+    fn execute_insufficient_scope() {
+        let server = Server::new(|_, _: &Request| {
+            Response::json(403, r#"{"message":"admin only"}"#)
+                .header("WWW-Authenticate", r#"Bearer error="insufficient_scope", scope="admin""#)
+        });
+        let authorized = authorized(&server);
+        let mut response = authorized.send(authorized.get(server.url("/resource"))).unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        let challenge = BearerChallenge::from_headers(response.headers()).unwrap();
+        assert!(challenge.is_insufficient_scope());
+        assert_eq!(Some(String::from("admin")), challenge.scope);
+        assert_eq!(r#"{"message":"admin only"}"#, response.text().unwrap());
+        assert_eq!(vec!["/resource"], paths(&server));
+    }
+
+    #[test]
+    fn execute_invalid_token_forbidden() {
+        let server = Server::new(|_, _: &Request| {
+            Response::json(403, "{}").header("WWW-Authenticate", r#"Bearer error="invalid_token""#)
+        });
+        let authorized = authorized(&server);
+        let response = authorized.send(authorized.get(server.url("/resource"))).unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        assert_eq!(vec!["/resource"], paths(&server));
+    }
+
+    #[test]
+    fn execute_dpop_nonce() {
+        let server = Server::new(|n, _: &Request| {
+            if n == 0 {
+                Response::json(401, "{}")
+                    .header("WWW-Authenticate", r#"DPoP error="use_dpop_nonce""#)
+                    .header("DPoP-Nonce", "eyJ7S_zG")
+            } else {
+                Response::json(200, "{}")
+            }
+        });
+        let provider = TestDPoP::on(&server);
+        let client = Client::new(provider, String::from("foo"), String::from("bar"), None)
+            .with_dpop(DPoPKey::generate());
+        let token: DPoP<Refresh> = token_with_refresh("DPoP", "aaaaaaaa", "bbbbbbbb", 3600);
+        let authorized = AuthorizedClient::new(client, reqwest::Client::new(), token);
+
+        let url = server.url("/resource?foo=bar");
+        let response = authorized.send(authorized.get(url)).unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        let requests = server.requests();
+        assert_eq!(vec!["/resource?foo=bar", "/resource?foo=bar"], paths(&server));
+        assert_eq!(Some("DPoP aaaaaaaa"), requests[0].header("Authorization"));
+        let claims = proof_claims(&requests[0]);
+        assert_eq!("GET", claims["htm"]);
+        assert_eq!(server.url("/resource").as_str(), claims["htu"]);
+        assert!(claims["ath"].is_string());
+        assert!(claims.get("nonce").is_none());
+        assert_eq!("eyJ7S_zG", proof_claims(&requests[1])["nonce"]);
+    }
+
+    #[test]
+    fn execute_dpop_nonce_unchanged() {
+        let server = Server::new(|_, _: &Request| {
+            Response::json(401, "{}").header("WWW-Authenticate", r#"DPoP error="use_dpop_nonce""#)
+        });
+        let provider = TestDPoP::on(&server);
+        let client = Client::new(provider, String::from("foo"), String::from("bar"), None)
+            .with_dpop(DPoPKey::generate());
+        let token: DPoP<Refresh> = token_with_refresh("DPoP", "aaaaaaaa", "bbbbbbbb", 3600);
+        let authorized = AuthorizedClient::new(client, reqwest::Client::new(), token);
+
+        let response = authorized.send(authorized.get(server.url("/resource"))).unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+        assert_eq!(1, server.hits());
+    }
+
+    #[test]
+    fn execute_dpop_without_key() {
+        let server = Server::new(|_, _: &Request| Response::json(200, "{}"));
+        let provider = TestDPoP::on(&server);
+        let client = Client::new(provider, String::from("foo"), String::from("bar"), None);
+        let token: DPoP<Refresh> = token_with_refresh("DPoP", "aaaaaaaa", "bbbbbbbb", 3600);
+        let authorized = AuthorizedClient::new(client, reqwest::Client::new(), token);
+
+        let result = authorized.send(authorized.get(server.url("/resource")));
+        match result.map_err(ClientError::into_kind) {
+            Err(ClientErrorKind::MissingDPoPKey) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(0, server.hits());
+    }
+
+    fn authorized_any(
+        server: &Server,
+        token_type: &str,
+        access_token: &str,
+    ) -> AuthorizedClient<TestAny> {
+        let provider = TestAny::on(server);
+        let client = Client::new(provider, String::from("foo"), String::from("bar"), None);
+        let token = token_with_refresh(token_type, access_token, "bbbbbbbb", 3600);
+        AuthorizedClient::new(client, reqwest::Client::new(), token)
+    }
+
+    #[test]
+    fn execute_unsupported_token_type() {
+        let server = Server::new(|_, _: &Request| Response::json(200, "{}"));
+        let authorized = authorized_any(&server, "N_A", "aaaaaaaa");
+        let result = authorized.send(authorized.get(server.url("/resource")));
+        match result.map_err(ClientError::into_kind) {
+            Err(ClientErrorKind::UnsupportedTokenType(TokenType::NotApplicable)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(0, server.hits());
+    }
+
+    #[test]
+    fn execute_invalid_header_value() {
+        let server = Server::new(|_, _: &Request| Response::json(200, "{}"));
+        let authorized = authorized_any(&server, "Bearer", "aaaa\naaaa");
+        let result = authorized.send(authorized.get(server.url("/resource")));
+        match result.map_err(ClientError::into_kind) {
+            Err(ClientErrorKind::InvalidHeaderValue(_)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(0, server.hits());
+    }
+}
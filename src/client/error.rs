@@ -1,16 +1,39 @@
+use std::any::Any;
 use std::error::Error;
 use std::{fmt, io};
 
-use reqwest;
+use reqwest::{self, header::InvalidHeaderValue};
 use serde_json;
-use url;
+use url::{self, Url};
 
+use client::raw::RawResponse;
 use client::response::ParseError;
-use error::OAuth2Error;
+use error::{BearerChallenge, OAuth2Error, Operation};
+use token::TokenType;
 
 /// Errors that can occur during authorization.
+///
+/// Errors from requests to the authorization server carry the operation which failed and the
+/// endpoint it was sent to.
+///
+/// `Display` describes only the operation and the kind of error. The underlying error, such as the
+/// `OAuth2Error` returned by the server, is the `source` of the error.
 #[derive(Debug)]
-pub enum ClientError {
+pub struct ClientError {
+    inner: Box<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    kind: ClientErrorKind,
+    operation: Option<Operation>,
+    endpoint: Option<Url>,
+    response: Option<Box<RawResponse>>,
+}
+
+/// Kinds of `ClientError`.
+#[derive(Debug)]
+pub enum ClientErrorKind {
     /// IO error.
     Io(io::Error),
 
@@ -23,47 +46,160 @@ pub enum ClientError {
     /// JSON error.
     Json(serde_json::Error),
 
+    /// Successful HTTP response whose body could not be decoded.
+    InvalidResponse(serde_json::Error, Box<RawResponse>),
+
+    /// Unsuccessful HTTP response without an OAuth 2.0 error body.
+    Http(Box<RawResponse>),
+
     /// Response parse error.
     Parse(ParseError),
 
     /// OAuth 2.0 error.
     OAuth2(OAuth2Error),
+
+    /// The token cannot be refreshed, since no refresh token was issued.
+    MissingRefreshToken,
+
+    /// The DPoP token cannot be sent, since the client has no DPoP key to sign proofs with.
+    MissingDPoPKey,
+
+    /// Tokens of the type cannot be sent in the `Authorization` header.
+    UnsupportedTokenType(TokenType),
+
+    /// The access token or DPoP proof is not a valid HTTP header value.
+    InvalidHeaderValue(InvalidHeaderValue),
+
+    /// Resource server Bearer token error.
+    ///
+    /// Returned by `AuthorizedClient` when the resource server still rejects the token as invalid
+    /// after it was refreshed, or when the request could not be replayed with a refreshed token.
+    Challenge(BearerChallenge),
+
+    /// A refreshed token could not be saved to the store.
+    ///
+    /// The previous refresh token may no longer be valid, so the refreshed token is kept with the
+    /// error saving it. Use `ClientError::unsaved_token` to recover it.
+    UnsavedToken(Box<dyn Any + Send + Sync>, Box<ClientError>),
+}
+
+impl ClientError {
+    /// Returns the kind of error.
+    pub fn kind(&self) -> &ClientErrorKind { &self.inner.kind }
+
+    /// Returns the kind of error, consuming the error.
+    pub fn into_kind(self) -> ClientErrorKind { self.inner.kind }
+
+    /// Returns the operation which failed, if the error came from the authorization server.
+    pub fn operation(&self) -> Option<Operation> { self.inner.operation }
+
+    /// Returns the endpoint of the operation which failed, if known.
+    pub fn endpoint(&self) -> Option<&Url> { self.inner.endpoint.as_ref() }
+
+    /// Returns the details of the HTTP response which caused the error, if any.
+    ///
+    /// Available for OAuth 2.0 error responses as well as unexpected responses, so that headers
+    /// such as `WWW-Authenticate` and `Retry-After` can be inspected.
+    pub fn response(&self) -> Option<&RawResponse> {
+        match self.inner.kind {
+            ClientErrorKind::InvalidResponse(_, ref raw) | ClientErrorKind::Http(ref raw) =>
+                Some(raw.as_ref()),
+            _ => self.inner.response.as_deref(),
+        }
+    }
+
+    /// Records the HTTP response which caused the error.
+    pub(crate) fn with_response(mut self, raw: Box<RawResponse>) -> Self {
+        self.inner.response = Some(raw);
+        self
+    }
+
+    /// Returns the refreshed token which could not be saved, if any.
+    ///
+    /// Returns `None` if the token is not of type `T`.
+    pub fn unsaved_token<T: 'static>(&self) -> Option<&T> {
+        match self.inner.kind {
+            ClientErrorKind::UnsavedToken(ref token, _) => token.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// Wraps an error saving a refreshed token, keeping the token.
+    pub(crate) fn unsaved<T: Send + Sync + 'static>(token: T, err: ClientError) -> Self {
+        ClientError::from(ClientErrorKind::UnsavedToken(Box::new(token), Box::new(err)))
+    }
+
+    /// Records the operation which failed and its endpoint.
+    pub(crate) fn with_context(mut self, operation: Operation, endpoint: &Url) -> Self {
+        if let ClientErrorKind::OAuth2(ref mut err) = self.inner.kind {
+            err.operation = Some(operation);
+            err.endpoint = Some(endpoint.clone());
+        }
+        self.inner.operation = Some(operation);
+        self.inner.endpoint = Some(endpoint.clone());
+        self
+    }
 }
 
 impl fmt::Display for ClientError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        match *self {
-            ClientError::Io(ref err) => write!(f, "{}", err),
-            ClientError::Url(ref err) => write!(f, "{}", err),
-            ClientError::Reqwest(ref err) => write!(f, "{}", err),
-            ClientError::Json(ref err) => write!(f, "{}", err),
-            ClientError::Parse(ref err) => write!(f, "{}", err),
-            ClientError::OAuth2(ref err) => write!(f, "{}", err),
+        match (self.operation(), self.endpoint()) {
+            (Some(operation), Some(endpoint)) => write!(f, "{} at {}: ", operation, endpoint)?,
+            (Some(operation), None) => write!(f, "{}: ", operation)?,
+            _ => {},
         }
+        write!(f, "{}", self.inner.kind)
+    }
+}
+
+/// Describes the kind of error only. The underlying error is the source of the `ClientError`.
+impl fmt::Display for ClientErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(match *self {
+            ClientErrorKind::Io(_) => "IO error",
+            ClientErrorKind::Url(_) => "URL error",
+            ClientErrorKind::Reqwest(_) => "HTTP request failed",
+            ClientErrorKind::Json(_) => "JSON error",
+            ClientErrorKind::InvalidResponse(..) => "Invalid response body",
+            ClientErrorKind::Http(_) => "HTTP error",
+            ClientErrorKind::Parse(_) => "Invalid response",
+            ClientErrorKind::OAuth2(_) => "OAuth 2.0 error",
+            ClientErrorKind::MissingRefreshToken => "No refresh token was issued",
+            ClientErrorKind::MissingDPoPKey => "No DPoP key to send the DPoP token with",
+            ClientErrorKind::UnsupportedTokenType(_) =>
+                "Token type cannot be sent in the Authorization header",
+            ClientErrorKind::InvalidHeaderValue(_) => "Token is not a valid header value",
+            ClientErrorKind::Challenge(_) => "Resource server rejected the token",
+            ClientErrorKind::UnsavedToken(..) => "Refreshed token could not be saved",
+        })
     }
 }
 
 impl Error for ClientError {
-    fn description(&self) -> &str {
-        match *self {
-            ClientError::Io(ref err) => err.description(),
-            ClientError::Url(ref err) => err.description(),
-            ClientError::Reqwest(ref err) => err.description(),
-            ClientError::Json(ref err) => err.description(),
-            ClientError::Parse(ref err) => err.description(),
-            ClientError::OAuth2(ref err) => err.description(),
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.inner.kind {
+            ClientErrorKind::Io(ref err) => Some(err),
+            ClientErrorKind::Url(ref err) => Some(err),
+            ClientErrorKind::Reqwest(ref err) => Some(err),
+            ClientErrorKind::Json(ref err) => Some(err),
+            ClientErrorKind::InvalidResponse(ref err, _) => Some(err),
+            ClientErrorKind::Http(ref raw) => Some(raw.as_ref()),
+            ClientErrorKind::Parse(ref err) => Some(err),
+            ClientErrorKind::OAuth2(ref err) => Some(err),
+            ClientErrorKind::InvalidHeaderValue(ref err) => Some(err),
+            ClientErrorKind::MissingRefreshToken
+            | ClientErrorKind::MissingDPoPKey
+            | ClientErrorKind::UnsupportedTokenType(_) => None,
+            ClientErrorKind::Challenge(ref err) => Some(err),
+            ClientErrorKind::UnsavedToken(_, ref err) => Some(err.as_ref()),
         }
     }
+}
 
-    fn cause(&self) -> Option<&Error> {
-        match *self {
-            ClientError::Io(ref err) => Some(err),
-            ClientError::Url(ref err) => Some(err),
-            ClientError::Reqwest(ref err) => Some(err),
-            ClientError::Json(ref err) => Some(err),
-            ClientError::Parse(ref err) => Some(err),
-            ClientError::OAuth2(ref err) => Some(err),
-        }
+impl From<ClientErrorKind> for ClientError {
+    fn from(kind: ClientErrorKind) -> Self {
+        let inner = Inner { kind, operation: None, endpoint: None, response: None };
+        ClientError { inner: Box::new(inner) }
     }
 }
 
@@ -71,15 +207,94 @@ macro_rules! impl_from {
     ($v:path, $t:ty) => {
         impl From<$t> for ClientError {
             fn from(err: $t) -> Self {
-                $v(err)
+                ClientError::from($v(err))
             }
         }
     }
 }
 
-impl_from!(ClientError::Io, io::Error);
-impl_from!(ClientError::Url, url::ParseError);
-impl_from!(ClientError::Reqwest, reqwest::Error);
-impl_from!(ClientError::Json, serde_json::Error);
-impl_from!(ClientError::Parse, ParseError);
-impl_from!(ClientError::OAuth2, OAuth2Error);
+impl_from!(ClientErrorKind::Io, io::Error);
+impl_from!(ClientErrorKind::Url, url::ParseError);
+impl_from!(ClientErrorKind::Reqwest, reqwest::Error);
+impl_from!(ClientErrorKind::Json, serde_json::Error);
+impl_from!(ClientErrorKind::Parse, ParseError);
+impl_from!(ClientErrorKind::OAuth2, OAuth2Error);
+impl_from!(ClientErrorKind::Challenge, BearerChallenge);
+impl_from!(ClientErrorKind::InvalidHeaderValue, InvalidHeaderValue);
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use url::Url;
+
+    use error::{OAuth2Error, OAuth2ErrorCode, Operation};
+    use super::{ClientError, ClientErrorKind};
+
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+
+    #[test]
+    fn send_sync() {
+        assert_send_sync::<ClientError>();
+        assert_send_sync::<OAuth2Error>();
+    }
+
+    #[test]
+    fn context() {
+        let endpoint = Url::parse("https://example.com/token").unwrap();
+        let err = OAuth2Error {
+            code: OAuth2ErrorCode::InvalidGrant,
+            description: None,
+            uri: None,
+            operation: None,
+            endpoint: None,
+        };
+        let err = ClientError::from(err).with_context(Operation::Refresh, &endpoint);
+
+        assert_eq!(Some(Operation::Refresh), err.operation());
+        assert_eq!(Some(&endpoint), err.endpoint());
+        assert_eq!("refresh at https://example.com/token: OAuth 2.0 error", err.to_string());
+
+        let source = err.source().unwrap().downcast_ref::<OAuth2Error>().unwrap();
+        assert_eq!(Some(Operation::Refresh), source.operation);
+        assert_eq!(Some(endpoint), source.endpoint);
+        match *err.kind() {
+            ClientErrorKind::OAuth2(ref err) => assert_eq!(OAuth2ErrorCode::InvalidGrant, err.code),
+            ref kind => panic!("unexpected kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn source_chain() {
+        let endpoint = Url::parse("https://example.com/token").unwrap();
+        let err = OAuth2Error {
+            code: OAuth2ErrorCode::InvalidGrant,
+            description: Some(String::from("Refresh token revoked")),
+            uri: None,
+            operation: None,
+            endpoint: None,
+        };
+        let err = ClientError::from(err).with_context(Operation::Refresh, &endpoint);
+
+        let mut messages = vec![err.to_string()];
+        let mut source = err.source();
+        while let Some(err) = source {
+            messages.push(err.to_string());
+            source = err.source();
+        }
+        assert_eq!(
+            vec![
+                "refresh at https://example.com/token: OAuth 2.0 error",
+                "invalid_grant: Refresh token revoked",
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn missing_refresh_token() {
+        let err = ClientError::from(ClientErrorKind::MissingRefreshToken);
+        assert!(err.source().is_none());
+        assert_eq!(None, err.operation());
+    }
+}
@@ -1,20 +1,31 @@
 //! Client.
 
 mod error;
+mod raw;
+mod retry;
 
 pub mod response;
-pub use self::error::ClientError;
+pub use self::error::{ClientError, ClientErrorKind};
+pub use self::raw::RawResponse;
+pub use self::retry::RetryPolicy;
 
-use reqwest;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use reqwest::{self, Body, Method};
 use reqwest::header::{ACCEPT, CONTENT_TYPE};
-use serde_json::{self, Value};
 use url::form_urlencoded::Serializer;
 use url::Url;
+use zeroize::Zeroizing;
 
 use client::response::FromResponse;
-use error::OAuth2Error;
+use dpop::{DPoPKey, DPOP};
+use error::{OAuth2Error, OAuth2ErrorCode, Operation};
 use provider::Provider;
-use token::{Lifetime, Refresh, Token};
+use secret::{Secret, SecretJson};
+use token::{Lifetime, Refreshable, Token};
 
 /// OAuth 2.0 client.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -26,10 +37,18 @@ pub struct Client<P> {
     pub client_id: String,
 
     /// Client secret.
-    pub client_secret: String,
+    pub client_secret: Secret,
 
     /// Redirect URI.
     pub redirect_uri: Option<String>,
+
+    /// DPoP key, if requests should carry DPoP proofs.
+    ///
+    /// See [RFC 9449](https://www.rfc-editor.org/rfc/rfc9449).
+    pub dpop: Option<Arc<DPoPKey>>,
+
+    /// Retry policy for token requests, if they should be retried.
+    pub retry: Option<RetryPolicy>,
 }
 
 impl<P: Provider> Client<P> {
@@ -57,11 +76,59 @@ impl<P: Provider> Client<P> {
         Client {
             provider,
             client_id,
-            client_secret,
+            client_secret: client_secret.into(),
             redirect_uri,
+            dpop: None,
+            retry: None,
         }
     }
 
+    /// Sends DPoP proofs signed with a key in token requests.
+    ///
+    /// Providers issuing DPoP-bound tokens should use `token::DPoP` as their token type.
+    ///
+    /// See [RFC 9449, section 5](https://www.rfc-editor.org/rfc/rfc9449#section-5).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use inth_oauth2::Client;
+    /// use inth_oauth2::dpop::DPoPKey;
+    /// use inth_oauth2::provider::google::Installed;
+    ///
+    /// let client = Client::new(
+    ///     Installed,
+    ///     String::from("CLIENT_ID"),
+    ///     String::from("CLIENT_SECRET"),
+    ///     Some(String::from("urn:ietf:wg:oauth:2.0:oob")),
+    /// ).with_dpop(DPoPKey::generate());
+    /// ```
+    pub fn with_dpop(mut self, key: DPoPKey) -> Self {
+        self.dpop = Some(Arc::new(key));
+        self
+    }
+
+    /// Retries token requests which failed transiently according to a policy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use inth_oauth2::Client;
+    /// use inth_oauth2::client::RetryPolicy;
+    /// use inth_oauth2::provider::google::Installed;
+    ///
+    /// let client = Client::new(
+    ///     Installed,
+    ///     String::from("CLIENT_ID"),
+    ///     String::from("CLIENT_SECRET"),
+    ///     Some(String::from("urn:ietf:wg:oauth:2.0:oob")),
+    /// ).with_retry(RetryPolicy::default());
+    /// ```
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
     /// Returns an authorization endpoint URI to direct the user to.
     ///
     /// See [RFC 6749, section 3.1](http://tools.ietf.org/html/rfc6749#section-3.1).
@@ -108,34 +175,106 @@ impl<P: Provider> Client<P> {
         uri
     }
 
+    /// Posts a token request, retrying according to the retry policy.
+    ///
+    /// Unless `retry_responses` is set, the request is only retried if it never reached the
+    /// server, as the grant may not be replayed once the server received it.
     fn post_token(
         &self,
         http_client: &reqwest::Client,
-        mut body: Serializer<String>,
-    ) -> Result<Value, ClientError> {
-        if self.provider.credentials_in_body() {
-            body.append_pair("client_id", &self.client_id);
-            body.append_pair("client_secret", &self.client_secret);
+        params: &[(&str, &str)],
+        retry_responses: bool,
+    ) -> Result<SecretJson, ClientError> {
+        let credentials = [
+            ("client_id", &self.client_id[..]),
+            ("client_secret", self.client_secret.expose()),
+        ];
+        let credentials = if self.provider.credentials_in_body() { &credentials[..] } else { &[] };
+        let params = params.iter().chain(credentials);
+
+        // Form encoding at most triples the length of each pair. Reserving that much up front
+        // keeps the buffer from being reallocated, which would leave copies of the secrets.
+        let capacity = params.clone()
+            .map(|&(name, value)| 3 * (name.len() + value.len()) + 2)
+            .sum();
+        let mut body = Zeroizing::new(String::with_capacity(capacity));
+        Serializer::new(&mut *body).extend_pairs(params).finish();
+
+        let body = SecretBody(Arc::new(body));
+        let mut retry = 0;
+
+        loop {
+            let mut retry_after = None;
+            let err = match self.post_token_once(http_client, &body, &mut retry_after) {
+                Ok(json) => return Ok(json),
+                Err(err) => err,
+            };
+            if !retry::is_retryable(&err, retry_responses) {
+                return Err(err);
+            }
+            match self.retry.and_then(|policy| policy.delay(retry, retry_after)) {
+                Some(delay) => thread::sleep(delay),
+                None => return Err(err),
+            }
+            retry += 1;
         }
+    }
 
-        let body = body.finish();
-
-        let mut response = http_client
-            .post(self.provider.token_uri().clone())
-            .basic_auth(&self.client_id, Some(&self.client_secret))
-            .header(ACCEPT, "application/json")
-            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
-            .body(body)
-            .send()?;
-
-        let json = serde_json::from_reader(&mut response)?;
-
-        let error = OAuth2Error::from_response(&json);
+    fn post_token_once(
+        &self,
+        http_client: &reqwest::Client,
+        body: &SecretBody,
+        retry_after: &mut Option<Duration>,
+    ) -> Result<SecretJson, ClientError> {
+        let token_uri = self.provider.token_uri();
+        let mut nonce_retried = false;
+
+        loop {
+            let mut request = http_client
+                .post(token_uri.clone())
+                .basic_auth(&self.client_id, Some(self.client_secret.expose()))
+                .header(ACCEPT, "application/json")
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+            if let Some(ref key) = self.dpop {
+                request = request.header(DPOP, key.proof(&Method::POST, token_uri, None));
+            }
 
-        if let Ok(error) = error {
-            Err(ClientError::from(error))
-        } else {
-            Ok(json)
+            let length = body.as_ref().len() as u64;
+            let mut response = request.body(Body::sized(Cursor::new(body.clone()), length)).send()?;
+
+            let nonce = self.dpop.as_ref()
+                .is_some_and(|key| key.record_nonce(token_uri, response.headers()));
+            *retry_after = retry::retry_after(response.headers());
+            let status = response.status();
+            // Sized from the Content-Length header so that reading does not reallocate the buffer,
+            // leaving copies of the tokens.
+            let length = response.content_length().map_or(0, |length| length.min(MAX_PRESIZE));
+            let mut bytes = Zeroizing::new(Vec::with_capacity(length as usize));
+            response.read_to_end(&mut bytes)?;
+            let raw = || Box::new(RawResponse::new(status, response.headers(), &bytes));
+
+            let content_type = response.headers().get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok());
+            let json = match response::parse_body(content_type, &bytes) {
+                Ok(json) => SecretJson(json),
+                Err(err) if status.is_success() =>
+                    return Err(ClientErrorKind::InvalidResponse(err, raw()).into()),
+                Err(_) => return Err(ClientErrorKind::Http(raw()).into()),
+            };
+
+            let error = OAuth2Error::from_response(&json);
+
+            match error {
+                // The server requires a nonce in the proof; retry once with the one it provided.
+                // See RFC 9449, section 8.
+                Ok(ref error)
+                    if nonce && !nonce_retried && error.code == OAuth2ErrorCode::UseDPoPNonce => {
+                    nonce_retried = true;
+                },
+                Ok(error) => return Err(ClientError::from(error).with_response(raw())),
+                Err(_) if !status.is_success() => return Err(ClientErrorKind::Http(raw()).into()),
+                Err(_) => return Ok(json),
+            }
         }
     }
 
@@ -147,23 +286,52 @@ impl<P: Provider> Client<P> {
         http_client: &reqwest::Client,
         code: &str,
     ) -> Result<P::Token, ClientError> {
-        let mut body = Serializer::new(String::new());
-        body.append_pair("grant_type", "authorization_code");
-        body.append_pair("code", code);
-
+        let mut params = vec![("grant_type", "authorization_code"), ("code", code)];
         if let Some(ref redirect_uri) = self.redirect_uri {
-            body.append_pair("redirect_uri", redirect_uri);
+            params.push(("redirect_uri", redirect_uri));
         }
 
-        let json = self.post_token(http_client, body)?;
-        let token = P::Token::from_response(&json)?;
+        let context = |err: ClientError| {
+            err.with_context(Operation::Token, self.provider.token_uri())
+        };
+        let json = self.post_token(http_client, &params, false).map_err(context)?;
+        let token = P::Token::from_response_with(&json, &self.provider.parse_policy())
+            .map_err(|err| context(err.into()))?;
         Ok(token)
     }
 }
 
-impl<P> Client<P> where P: Provider, P::Token: Token<Refresh> {
+/// Largest token response buffer allocated up front, whatever the Content-Length.
+const MAX_PRESIZE: u64 = 1 << 20;
+
+/// A request body holding secrets, zeroed in memory once no request is sending it.
+#[derive(Clone)]
+struct SecretBody(Arc<Zeroizing<String>>);
+
+impl AsRef<[u8]> for SecretBody {
+    fn as_ref(&self) -> &[u8] { self.0.as_bytes() }
+}
+
+/// The result of refreshing an access token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refreshed<T> {
+    /// The newly issued token.
+    pub token: T,
+
+    /// Whether the authorization server issued a new refresh token.
+    ///
+    /// When the refresh token rotates, the previous one may no longer be valid, so the new token
+    /// should be persisted before it is used.
+    ///
+    /// See [RFC 6749, section 6](http://tools.ietf.org/html/rfc6749#section-6).
+    pub rotated: bool,
+}
+
+impl<P> Client<P> where P: Provider, P::Lifetime: Refreshable {
     /// Refreshes an access token.
     ///
+    /// Returns `ClientErrorKind::MissingRefreshToken` if no refresh token was issued.
+    ///
     /// See [RFC 6749, section 6](http://tools.ietf.org/html/rfc6749#section-6).
     pub fn refresh_token(
         &self,
@@ -171,20 +339,46 @@ impl<P> Client<P> where P: Provider, P::Token: Token<Refresh> {
         token: P::Token,
         scope: Option<&str>,
     ) -> Result<P::Token, ClientError> {
-        let mut body = Serializer::new(String::new());
-        body.append_pair("grant_type", "refresh_token");
-        body.append_pair("refresh_token", token.lifetime().refresh_token());
+        self.refresh(http_client, &token, scope).map(|refreshed| refreshed.token)
+    }
 
+    /// Refreshes an access token, reporting whether the refresh token was rotated.
+    ///
+    /// Returns `ClientErrorKind::MissingRefreshToken` if no refresh token was issued.
+    ///
+    /// See [RFC 6749, section 6](http://tools.ietf.org/html/rfc6749#section-6).
+    pub fn refresh(
+        &self,
+        http_client: &reqwest::Client,
+        token: &P::Token,
+        scope: Option<&str>,
+    ) -> Result<Refreshed<P::Token>, ClientError> {
+        let refresh_token = token.lifetime().refresh_token()
+            .ok_or_else(|| {
+                ClientError::from(ClientErrorKind::MissingRefreshToken)
+                    .with_context(Operation::Refresh, self.provider.token_uri())
+            })?;
+
+        let mut params = vec![("grant_type", "refresh_token"), ("refresh_token", refresh_token)];
         if let Some(scope) = scope {
-            body.append_pair("scope", scope);
+            params.push(("scope", scope));
         }
 
-        let json = self.post_token(http_client, body)?;
-        let token = P::Token::from_response_inherit(&json, &token)?;
-        Ok(token)
+        let context = |err: ClientError| {
+            err.with_context(Operation::Refresh, self.provider.token_uri())
+        };
+        let json = self.post_token(http_client, &params, true).map_err(context)?;
+        let policy = self.provider.parse_policy();
+        let refreshed = P::Token::from_response_inherit_with(&json, token, &policy)
+            .map_err(|err| context(err.into()))?;
+        let rotated = refreshed.lifetime().refresh_token() != token.lifetime().refresh_token();
+        Ok(Refreshed { token: refreshed, rotated })
     }
 
     /// Ensures an access token is valid by refreshing it if necessary.
+    ///
+    /// Returns `ClientErrorKind::MissingRefreshToken` if the token has expired and no refresh token
+    /// was issued.
     pub fn ensure_token(
         &self,
         http_client: &reqwest::Client,
@@ -200,33 +394,48 @@ impl<P> Client<P> where P: Provider, P::Token: Token<Refresh> {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use reqwest::{self, StatusCode};
     use url::Url;
-    use token::{Bearer, Static};
-    use provider::Provider;
+    use client::response::ParsePolicy;
+    use client::{ClientError, ClientErrorKind, RetryPolicy};
+    use dpop::DPoPKey;
+    use error::{OAuth2ErrorCode, Operation};
+    use test_support::{
+        jwt, proof_claims, token_with_refresh, Request, Response, Server, TestProvider,
+    };
+    use token::{Bearer, DPoP, Flexible, Lifetime, Refresh, Static, Token};
     use super::Client;
 
-    struct Test {
-        auth_uri: Url,
-        token_uri: Url
-    }
-    impl Provider for Test {
-        type Lifetime = Static;
-        type Token = Bearer<Static>;
-        fn auth_uri(&self) -> &Url { &self.auth_uri }
-        fn token_uri(&self) -> &Url { &self.token_uri }
-    }
-    impl Test {
-        fn new() -> Self {
-            Test {
-                auth_uri: Url::parse("http://example.com/oauth2/auth").unwrap(),
-                token_uri: Url::parse("http://example.com/oauth2/token").unwrap()
-            }
-        }
+    type Test = TestProvider<Bearer<Static>, Static>;
+    type TestDPoP = TestProvider<DPoP<Static>, Static>;
+    type TestRefresh = TestProvider<Bearer<Refresh>, Refresh>;
+    type TestFlexible = TestProvider<Bearer<Flexible>, Flexible>;
+
+    fn example() -> Test {
+        Test::new(
+            Url::parse("http://example.com/oauth2/auth").unwrap(),
+            Url::parse("http://example.com/oauth2/token").unwrap(),
+        )
+    }
+
+    fn retrying(server: &Server) -> Client<TestRefresh> {
+        let mut provider = TestRefresh::on(server);
+        provider.policy = ParsePolicy::strict();
+        Client::new(provider, String::from("foo"), String::from("bar"), None).with_retry(
+            RetryPolicy::new(2).with_backoff(Duration::from_millis(1), Duration::from_millis(10))
+        )
+    }
+
+    fn refresh_token() -> Bearer<Refresh> {
+        token_with_refresh("Bearer", "aaaaaaaa", "bbbbbbbb", 0)
     }
 
     #[test]
     fn auth_uri() {
-        let client = Client::new(Test::new(), String::from("foo"), String::from("bar"), None);
+        let client = Client::new(example(), String::from("foo"), String::from("bar"), None);
         assert_eq!(
             "http://example.com/oauth2/auth?response_type=code&client_id=foo",
             client.auth_uri(None, None).as_str()
@@ -236,7 +445,7 @@ mod tests {
     #[test]
     fn auth_uri_with_redirect_uri() {
         let client = Client::new(
-            Test::new(),
+            example(),
             String::from("foo"),
             String::from("bar"),
             Some(String::from("http://example.com/oauth2/callback")),
@@ -249,7 +458,7 @@ mod tests {
 
     #[test]
     fn auth_uri_with_scope() {
-        let client = Client::new(Test::new(), String::from("foo"), String::from("bar"), None);
+        let client = Client::new(example(), String::from("foo"), String::from("bar"), None);
         assert_eq!(
             "http://example.com/oauth2/auth?response_type=code&client_id=foo&scope=baz",
             client.auth_uri(Some("baz"), None).as_str()
@@ -258,10 +467,275 @@ mod tests {
 
     #[test]
     fn auth_uri_with_state() {
-        let client = Client::new(Test::new(), String::from("foo"), String::from("bar"), None);
+        let client = Client::new(example(), String::from("foo"), String::from("bar"), None);
         assert_eq!(
             "http://example.com/oauth2/auth?response_type=code&client_id=foo&state=baz",
             client.auth_uri(None, Some("baz")).as_str()
         );
     }
+
+    #[test]
+    fn request_token_dpop_nonce() {
+        let server = Server::new(|n, _: &Request| {
+            if n == 0 {
+                Response::json(400, r#"{"error":"use_dpop_nonce"}"#).header("DPoP-Nonce", "eyJ7S_zG")
+            } else {
+                Response::json(200, r#"{"token_type":"DPoP","access_token":"aaaaaaaa"}"#)
+            }
+        });
+        let provider = TestDPoP::on(&server);
+        let client = Client::new(provider, String::from("foo"), String::from("bar"), None)
+            .with_dpop(DPoPKey::generate());
+
+        let token = client.request_token(&reqwest::Client::new(), "code").unwrap();
+        assert_eq!("aaaaaaaa", token.access_token());
+
+        let requests = server.requests();
+        assert_eq!(2, requests.len());
+        let claims = proof_claims(&requests[0]);
+        assert_eq!("POST", claims["htm"]);
+        assert_eq!(server.url("/token").as_str(), claims["htu"]);
+        assert!(claims.get("nonce").is_none());
+        assert_eq!("eyJ7S_zG", proof_claims(&requests[1])["nonce"]);
+        assert_ne!(claims["jti"], proof_claims(&requests[1])["jti"]);
+    }
+
+    #[test]
+    fn request_token_http_error() {
+        let server = Server::new(|_, _: &Request| {
+            Response::json(502, "<html>Bad Gateway</html>").set_header("Content-Type", "text/html")
+        });
+        let client = Client::new(
+            Test::on(&server),
+            String::from("foo"),
+            String::from("bar"),
+            None,
+        );
+        let result = client.request_token(&reqwest::Client::new(), "code");
+        match result.map_err(ClientError::into_kind) {
+            Err(ClientErrorKind::Http(ref raw)) => {
+                assert_eq!(StatusCode::BAD_GATEWAY, raw.status);
+                assert_eq!(Some("text/html"), raw.content_type());
+                assert_eq!("<html>Bad Gateway</html>", raw.body);
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn request_token_http_error_json() {
+        let server = Server::new(|_, _: &Request| Response::json(503, r#"{"message":"down"}"#));
+        let client = Client::new(
+            Test::on(&server),
+            String::from("foo"),
+            String::from("bar"),
+            None,
+        );
+        let err = client.request_token(&reqwest::Client::new(), "code").unwrap_err();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, err.response().unwrap().status);
+    }
+
+    #[test]
+    fn request_token_oauth2_error_response() {
+        let server = Server::new(|_, _: &Request| {
+            Response::json(401, r#"{"error":"invalid_client"}"#)
+                .header("WWW-Authenticate", r#"Basic realm="example""#)
+        });
+        let client = Client::new(Test::on(&server), String::from("foo"), String::from("bar"), None);
+        let err = client.request_token(&reqwest::Client::new(), "code").unwrap_err();
+        let raw = err.response().unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, raw.status);
+        assert_eq!(r#"Basic realm="example""#, raw.headers["WWW-Authenticate"]);
+        assert_eq!(r#"{"error":"invalid_client"}"#, raw.body);
+        match err.into_kind() {
+            ClientErrorKind::OAuth2(ref err) => assert_eq!(OAuth2ErrorCode::InvalidClient, err.code),
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn request_token_invalid_response() {
+        let server = Server::new(|_, _: &Request| Response::json(200, "access_token=aaaaaaaa"));
+        let client = Client::new(
+            Test::on(&server),
+            String::from("foo"),
+            String::from("bar"),
+            None,
+        );
+        let result = client.request_token(&reqwest::Client::new(), "code");
+        match result.map_err(ClientError::into_kind) {
+            Err(ClientErrorKind::InvalidResponse(_, ref raw)) => {
+                assert_eq!(StatusCode::OK, raw.status);
+                assert_eq!("", raw.body);
+                assert!(!format!("{:?}", raw).contains("aaaaaaaa"));
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn refresh_retries() {
+        let server = Server::new(|n, _: &Request| match n {
+            0 => Response::json(503, "Unavailable").set_header("Content-Type", "text/plain"),
+            1 => Response::json(429, "{}").header("Retry-After", "0"),
+            _ => Response::json(
+                200,
+                r#"{"token_type":"Bearer","access_token":"cccccccc","expires_in":3600}"#,
+            ),
+        });
+        let client = retrying(&server);
+        let refreshed = client.refresh(&reqwest::Client::new(), &refresh_token(), None).unwrap();
+        assert_eq!("cccccccc", refreshed.token.access_token());
+        assert_eq!(3, server.hits());
+    }
+
+    #[test]
+    fn refresh_retries_temporarily_unavailable() {
+        let server = Server::new(|_, _: &Request| {
+            Response::json(400, r#"{"error":"temporarily_unavailable"}"#)
+        });
+        let client = retrying(&server);
+        let err = client.refresh(&reqwest::Client::new(), &refresh_token(), None).unwrap_err();
+        assert_eq!(Some(Operation::Refresh), err.operation());
+        assert_eq!(Some(&server.url("/token")), err.endpoint());
+        assert_eq!(StatusCode::BAD_REQUEST, err.response().unwrap().status);
+        match err.into_kind() {
+            ClientErrorKind::OAuth2(ref err)
+                if err.code == OAuth2ErrorCode::TemporarilyUnavailable => {
+                assert_eq!(Some(Operation::Refresh), err.operation);
+            },
+            kind => panic!("unexpected error {:?}", kind),
+        }
+        assert_eq!(3, server.hits());
+    }
+
+    #[test]
+    fn refresh_retry_after_too_long() {
+        let server = Server::new(|_, _: &Request| {
+            Response::json(429, "{}").header("Retry-After", "3600")
+        });
+        let client = retrying(&server);
+        assert!(client.refresh(&reqwest::Client::new(), &refresh_token(), None).is_err());
+        assert_eq!(1, server.hits());
+    }
+
+    #[test]
+    fn request_token_not_retried() {
+        let server = Server::new(|_, _: &Request| Response::json(503, "{}"));
+        let client = retrying(&server);
+        let err = client.request_token(&reqwest::Client::new(), "code").unwrap_err();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, err.response().unwrap().status);
+        assert_eq!(1, server.hits());
+    }
+
+    #[test]
+    fn request_token_not_retried_temporarily_unavailable() {
+        let server = Server::new(|_, _: &Request| {
+            Response::json(400, r#"{"error":"temporarily_unavailable"}"#)
+        });
+        let client = retrying(&server);
+        let err = client.request_token(&reqwest::Client::new(), "code").unwrap_err();
+        match err.into_kind() {
+            ClientErrorKind::OAuth2(ref err)
+                if err.code == OAuth2ErrorCode::TemporarilyUnavailable => {},
+            kind => panic!("unexpected error {:?}", kind),
+        }
+        assert_eq!(1, server.hits());
+    }
+
+    #[test]
+    fn refresh_not_retried_after_server_error() {
+        let server = Server::new(|n, _: &Request| match n {
+            0 => Response::json(500, r#"{"error":"server_error"}"#),
+            _ => Response::json(
+                200,
+                r#"{"token_type":"Bearer","access_token":"cccccccc","expires_in":3600}"#,
+            ),
+        });
+        let client = retrying(&server);
+        let err = client.refresh(&reqwest::Client::new(), &refresh_token(), None).unwrap_err();
+        match *err.kind() {
+            ClientErrorKind::OAuth2(ref err) => assert_eq!(OAuth2ErrorCode::ServerError, err.code),
+            ref kind => panic!("unexpected error {:?}", kind),
+        }
+        assert_eq!(1, server.hits());
+    }
+
+    #[test]
+    fn refresh_form_response() {
+        let server = Server::new(|_, _: &Request| {
+            Response::json(200, "access_token=cccccccc&token_type=bearer&expires_in=3600")
+                .set_header("Content-Type", "application/x-www-form-urlencoded; charset=utf-8")
+        });
+        let client = retrying(&server);
+        let refreshed = client.refresh(&reqwest::Client::new(), &refresh_token(), None).unwrap();
+        assert_eq!("cccccccc", refreshed.token.access_token());
+        assert!(!refreshed.token.lifetime().expired());
+        assert_eq!("bbbbbbbb", refreshed.token.lifetime().refresh_token());
+    }
+
+    #[test]
+    fn refresh_lenient() {
+        let server = Server::new(|_, _: &Request| {
+            Response::json(
+                200,
+                r#"{"token_type":"BEARER","access_token":"cccccccc","expires_in":"3600"}"#,
+            )
+        });
+        let mut client = retrying(&server);
+        assert!(client.refresh(&reqwest::Client::new(), &refresh_token(), None).is_err());
+
+        client.provider.policy = ParsePolicy::lenient();
+        let refreshed = client.refresh(&reqwest::Client::new(), &refresh_token(), None).unwrap();
+        assert_eq!("cccccccc", refreshed.token.access_token());
+        assert!(!refreshed.token.lifetime().expired());
+    }
+
+    #[test]
+    fn refresh_jwt_expiry() {
+        let server = Server::new(|_, _: &Request| {
+            let json = json!({
+                "token_type": "Bearer",
+                "access_token": jwt(r#"{"iat":1700000000,"exp":4102444800}"#),
+            });
+            Response::json(200, &json.to_string())
+        });
+        let mut client = retrying(&server);
+        client.provider.policy = ParsePolicy::strict().with_jwt_expiry(true);
+        let http = reqwest::Client::new();
+
+        let token = client.ensure_token(&http, refresh_token()).unwrap();
+        assert_eq!(Some(Utc.timestamp_opt(4102444800, 0).unwrap()), token.lifetime().expires_at());
+        assert_eq!(Some(Utc.timestamp_opt(1700000000, 0).unwrap()), token.lifetime().issued_at());
+        assert_eq!("bbbbbbbb", token.lifetime().refresh_token());
+    }
+
+    #[test]
+    fn refresh_flexible() {
+        let server = Server::new(|n, _: &Request| match n {
+            0 => Response::json(
+                200,
+                r#"{"token_type":"Bearer","access_token":"aaaaaaaa","expires_in":0}"#,
+            ),
+            _ => Response::json(200, r#"{"token_type":"Bearer","access_token":"cccccccc"}"#),
+        });
+        let client = Client::new(TestFlexible::on(&server), String::from("foo"), String::from("bar"), None);
+        let http = reqwest::Client::new();
+
+        let token = client.request_token(&http, "code").unwrap();
+        assert_eq!(None, token.lifetime().refresh_token());
+        assert!(token.lifetime().expires().is_some());
+        match client.ensure_token(&http, token).map_err(ClientError::into_kind) {
+            Err(ClientErrorKind::MissingRefreshToken) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(1, server.hits());
+
+        let token: Bearer<Flexible> = token_with_refresh("Bearer", "aaaaaaaa", "bbbbbbbb", 0);
+        let refreshed = client.refresh(&http, &token, None).unwrap();
+        assert_eq!("cccccccc", refreshed.token.access_token());
+        assert_eq!(Some("bbbbbbbb"), refreshed.token.lifetime().refresh_token());
+        assert_eq!(None, refreshed.token.lifetime().expires());
+        assert!(!refreshed.rotated);
+    }
 }
@@ -0,0 +1,116 @@
+//! Raw HTTP response details.
+
+use std::error::Error;
+use std::fmt;
+
+use reqwest::header::{HeaderMap, HeaderName, CONTENT_TYPE, RETRY_AFTER, WWW_AUTHENTICATE};
+use reqwest::StatusCode;
+
+/// The maximum number of body bytes retained.
+pub const BODY_LIMIT: usize = 4096;
+
+/// Headers retained, besides `Content-Type`, `Retry-After` and `WWW-Authenticate`.
+const RETAINED_HEADERS: &[&str] = &["date", "x-request-id", "x-correlation-id"];
+
+/// Details of an unexpected HTTP response.
+///
+/// Only headers useful for diagnosing errors are retained, and the body is truncated to
+/// `BODY_LIMIT` bytes. The body of a successful response is not retained, since it may hold
+/// tokens.
+#[derive(Debug, Clone)]
+pub struct RawResponse {
+    /// Status code.
+    pub status: StatusCode,
+
+    /// Selected headers.
+    pub headers: HeaderMap,
+
+    /// Body, decoded as UTF-8 with invalid sequences replaced.
+    ///
+    /// Empty for successful responses.
+    pub body: String,
+
+    /// Whether the body was truncated.
+    pub truncated: bool,
+}
+
+impl RawResponse {
+    pub(crate) fn new(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Self {
+        let retained = [CONTENT_TYPE, RETRY_AFTER, WWW_AUTHENTICATE].iter().cloned()
+            .chain(RETAINED_HEADERS.iter().map(|name| HeaderName::from_static(name)));
+        let mut selected = HeaderMap::new();
+        for name in retained {
+            for value in headers.get_all(&name) {
+                selected.append(name.clone(), value.clone());
+            }
+        }
+
+        let body = if status.is_success() { &[][..] } else { body };
+        let truncated = body.len() > BODY_LIMIT;
+        let body = &body[..body.len().min(BODY_LIMIT)];
+
+        RawResponse {
+            status,
+            headers: selected,
+            body: String::from_utf8_lossy(body).into_owned(),
+            truncated,
+        }
+    }
+
+    /// Returns the `Content-Type` header, if present.
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok())
+    }
+}
+
+impl fmt::Display for RawResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Unexpected HTTP response {}", self.status)?;
+        if let Some(content_type) = self.content_type() {
+            write!(f, " ({})", content_type)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for RawResponse {}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::StatusCode;
+
+    use super::{RawResponse, BODY_LIMIT};
+
+    #[test]
+    fn new() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", HeaderValue::from_static("text/html"));
+        headers.insert("X-Request-Id", HeaderValue::from_static("abc"));
+        headers.insert("Set-Cookie", HeaderValue::from_static("session=secret"));
+        let raw = RawResponse::new(StatusCode::BAD_GATEWAY, &headers, b"<html>Bad Gateway</html>");
+
+        assert_eq!(StatusCode::BAD_GATEWAY, raw.status);
+        assert_eq!(Some("text/html"), raw.content_type());
+        assert_eq!("abc", raw.headers["x-request-id"]);
+        assert!(raw.headers.get("set-cookie").is_none());
+        assert_eq!("<html>Bad Gateway</html>", raw.body);
+        assert!(!raw.truncated);
+        assert_eq!("Unexpected HTTP response 502 Bad Gateway (text/html)", raw.to_string());
+    }
+
+    #[test]
+    fn new_truncated() {
+        let body = vec![b'a'; BODY_LIMIT + 1];
+        let raw = RawResponse::new(StatusCode::BAD_GATEWAY, &HeaderMap::new(), &body);
+        assert_eq!(BODY_LIMIT, raw.body.len());
+        assert!(raw.truncated);
+    }
+
+    #[test]
+    fn new_success() {
+        let raw = RawResponse::new(StatusCode::OK, &HeaderMap::new(), b"access_token=aaaaaaaa");
+        assert_eq!("", raw.body);
+        assert!(!format!("{:?}", raw).contains("aaaaaaaa"));
+    }
+}
@@ -1,9 +1,20 @@
 //! Response parsing.
 
+use std::borrow::Cow;
 use std::error::Error;
 use std::fmt;
+use std::sync::Arc;
 
-use serde_json::Value;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde_json::{self, Map, Value};
+use url::form_urlencoded;
+
+use secret::{may_be_secret, Secret};
+
+/// Form fields converted to numbers when parsing form-encoded responses.
+const NUMERIC_FIELDS: &[&str] = &["expires_in", "refresh_token_expires_in"];
 
 /// Response parsing.
 pub trait FromResponse: Sized {
@@ -18,39 +29,559 @@ pub trait FromResponse: Sized {
     fn from_response_inherit(json: &Value, prev: &Self) -> Result<Self, ParseError> {
         FromResponse::from_response(json)
     }
+
+    /// Parse a JSON response according to a parsing policy.
+    ///
+    /// The default implementation ignores the policy.
+    #[allow(unused_variables)]
+    fn from_response_with(json: &Value, policy: &ParsePolicy) -> Result<Self, ParseError> {
+        FromResponse::from_response(json)
+    }
+
+    /// Parse a JSON response according to a parsing policy, inheriting missing values from the
+    /// previous instance.
+    ///
+    /// The default implementation ignores the policy.
+    #[allow(unused_variables)]
+    fn from_response_inherit_with(
+        json: &Value,
+        prev: &Self,
+        policy: &ParsePolicy,
+    ) -> Result<Self, ParseError> {
+        FromResponse::from_response_inherit(json, prev)
+    }
+}
+
+/// The issue and expiry times of an access token.
+pub type Expiry = (DateTime<Utc>, DateTime<Utc>);
+
+/// Policy for parsing token responses.
+///
+/// The strict policy, which is the default, rejects responses which do not conform to RFC 6749.
+/// The lenient policy accepts common deviations of real providers:
+///
+/// - numbers sent as strings, such as `"expires_in":"3600"`,
+/// - token types differing in case from their registered names, such as `BEARER`,
+/// - fields unexpected for the token lifetime, such as a `refresh_token` for an `Expiring` token,
+///   which are ignored with a warning.
+///
+/// Either policy can also derive the lifetime of JWT access tokens from their claims when the
+/// response omits `expires_in`. See `with_jwt_expiry`.
+///
+/// Providers choose a policy with `Provider::parse_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ParsePolicy {
+    lenient: bool,
+    jwt_expiry: bool,
+}
+
+impl ParsePolicy {
+    /// Returns the strict policy.
+    pub fn strict() -> Self {
+        ParsePolicy { lenient: false, jwt_expiry: false }
+    }
+
+    /// Returns the lenient policy.
+    pub fn lenient() -> Self {
+        ParsePolicy { lenient: true, jwt_expiry: false }
+    }
+
+    /// Sets whether to derive the lifetime from the `exp` and `iat` claims of JWT access tokens
+    /// when the response omits `expires_in`.
+    ///
+    /// The access token is decoded but not verified, so this is only as trustworthy as the
+    /// connection to the authorization server.
+    ///
+    /// See [RFC 9068, section 2.2](https://www.rfc-editor.org/rfc/rfc9068#section-2.2).
+    pub fn with_jwt_expiry(mut self, jwt_expiry: bool) -> Self {
+        self.jwt_expiry = jwt_expiry;
+        self
+    }
+
+    /// Returns true if the policy is lenient.
+    pub fn is_lenient(&self) -> bool { self.lenient }
+
+    /// Returns true if the lifetime of JWT access tokens may be derived from their claims.
+    pub fn jwt_expiry(&self) -> bool { self.jwt_expiry }
+
+    /// Returns the issue and expiry times of the access token, if the response includes
+    /// `expires_in`.
+    ///
+    /// If it does not and JWT expiry is enabled, the times are taken from the `iat` and `exp`
+    /// claims of the access token instead. The issue time defaults to now.
+    pub fn expiry(
+        &self,
+        obj: &Map<String, Value>,
+    ) -> Result<Option<Expiry>, ParseError> {
+        let now = Utc::now();
+        if let Some(expires_in) = self.i64_field(obj, "expires_in")? {
+            return Ok(Some((now, now + Duration::seconds(expires_in))));
+        }
+        if !self.jwt_expiry {
+            return Ok(None);
+        }
+
+        let claims = match obj.get("access_token").and_then(Value::as_str).and_then(jwt_claims) {
+            Some(claims) => claims,
+            None => return Ok(None),
+        };
+        let timestamp = |key| claims.get(key)
+            .and_then(Value::as_i64)
+            .and_then(|t| Utc.timestamp_opt(t, 0).single());
+        Ok(timestamp("exp").map(|expires| (timestamp("iat").unwrap_or(now), expires)))
+    }
+
+    /// Returns an integer field, if present.
+    ///
+    /// The lenient policy also accepts strings containing integers.
+    pub fn i64_field(
+        &self,
+        obj: &Map<String, Value>,
+        key: &str,
+    ) -> Result<Option<i64>, ParseError> {
+        let error = |value| {
+            ParseError::expected_field_type(key.to_owned(), "i64").with_value(value)
+        };
+        match obj.get(key) {
+            None => Ok(None),
+            Some(Value::String(s)) if self.lenient => s.trim().parse()
+                .map(Some)
+                .map_err(|err| error(&obj[key]).with_source(err)),
+            Some(value) => value.as_i64()
+                .map(Some)
+                .ok_or_else(|| error(value)),
+        }
+    }
+
+    /// Checks the `token_type` field against a registered token type name.
+    ///
+    /// The strict policy accepts the registered name and its lowercase form. The lenient policy
+    /// ignores case entirely.
+    pub fn check_token_type(
+        &self,
+        obj: &Map<String, Value>,
+        expected: &'static str,
+    ) -> Result<(), ParseError> {
+        let token_type = str_field(obj, "token_type")?;
+        let matches = if self.lenient {
+            token_type.eq_ignore_ascii_case(expected)
+        } else {
+            token_type == expected || token_type == expected.to_lowercase()
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(ParseError::expected_field_value("token_type", expected)
+                .with_value(&obj["token_type"]))
+        }
+    }
+
+    /// Rejects an unexpected field if it is present.
+    ///
+    /// The lenient policy logs a warning instead.
+    pub fn check_unexpected(
+        &self,
+        obj: &Map<String, Value>,
+        key: &str,
+    ) -> Result<(), ParseError> {
+        if !obj.contains_key(key) {
+            Ok(())
+        } else if self.lenient {
+            warn!("ignoring unexpected field {} in token response", key);
+            Ok(())
+        } else {
+            Err(ParseError::unexpected_field(key.to_owned()).with_value(&obj[key]))
+        }
+    }
+}
+
+/// Response parse error kinds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// Expected response to be of type.
+    ExpectedType(Cow<'static, str>),
+
+    /// Expected field to be of type.
+    ExpectedFieldType(Cow<'static, str>, Cow<'static, str>),
+
+    /// Expected field to equal value.
+    ExpectedFieldValue(Cow<'static, str>, Cow<'static, str>),
+
+    /// Expected field to not be present.
+    UnexpectedField(Cow<'static, str>),
 }
 
 /// Response parse errors.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ParseError {
+///
+/// Errors may carry the offending value, which is redacted if the field might hold a secret, and
+/// the underlying error. Errors compare equal if they are of the same kind.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    value: Option<String>,
+    source: Option<Arc<dyn Error + Send + Sync>>,
+}
+
+impl ParseError {
     /// Expected response to be of type.
-    ExpectedType(&'static str),
+    pub fn expected_type<T>(expected: T) -> Self
+    where T: Into<Cow<'static, str>> {
+        ParseError::from(ParseErrorKind::ExpectedType(expected.into()))
+    }
 
     /// Expected field to be of type.
-    ExpectedFieldType(&'static str, &'static str),
+    pub fn expected_field_type<K, T>(field: K, expected: T) -> Self
+    where K: Into<Cow<'static, str>>, T: Into<Cow<'static, str>> {
+        ParseError::from(ParseErrorKind::ExpectedFieldType(field.into(), expected.into()))
+    }
 
     /// Expected field to equal value.
-    ExpectedFieldValue(&'static str, &'static str),
+    pub fn expected_field_value<K, V>(field: K, expected: V) -> Self
+    where K: Into<Cow<'static, str>>, V: Into<Cow<'static, str>> {
+        ParseError::from(ParseErrorKind::ExpectedFieldValue(field.into(), expected.into()))
+    }
 
     /// Expected field to not be present.
-    UnexpectedField(&'static str),
+    pub fn unexpected_field<K>(field: K) -> Self
+    where K: Into<Cow<'static, str>> {
+        ParseError::from(ParseErrorKind::UnexpectedField(field.into()))
+    }
+
+    /// Attaches the value received.
+    ///
+    /// Values of fields which might hold secrets, such as tokens, are replaced by a fingerprint.
+    /// For errors about the whole response, only the type of the value is kept.
+    pub fn with_value(self, value: &Value) -> Self {
+        let redact = self.field().is_some_and(may_be_secret);
+        self.with_value_redacted(value, redact)
+    }
+
+    /// Attaches the value received, always replacing it by a fingerprint.
+    pub fn with_secret_value(self, value: &Value) -> Self {
+        self.with_value_redacted(value, true)
+    }
+
+    fn with_value_redacted(mut self, value: &Value, redact: bool) -> Self {
+        let json = value.to_string();
+        self.value = Some(if redact {
+            Secret::from(json).to_string()
+        } else if self.field().is_none() {
+            String::from(json_type(value))
+        } else if json.chars().count() > VALUE_LIMIT {
+            format!("{}...", json.chars().take(VALUE_LIMIT).collect::<String>())
+        } else {
+            json
+        });
+        self
+    }
+
+    /// Attaches the underlying error.
+    pub fn with_source<E>(mut self, source: E) -> Self
+    where E: Error + Send + Sync + 'static {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    /// Returns the kind of error.
+    pub fn kind(&self) -> &ParseErrorKind { &self.kind }
+
+    /// Returns the name of the field in error, if the error concerns a field.
+    pub fn field(&self) -> Option<&str> {
+        match self.kind {
+            ParseErrorKind::ExpectedType(_) => None,
+            ParseErrorKind::ExpectedFieldType(ref k, _)
+            | ParseErrorKind::ExpectedFieldValue(ref k, _)
+            | ParseErrorKind::UnexpectedField(ref k) => Some(k),
+        }
+    }
+
+    /// Returns the value received, possibly redacted, if known.
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_ref().map(|s| &s[..])
+    }
 }
 
+impl From<ParseErrorKind> for ParseError {
+    fn from(kind: ParseErrorKind) -> ParseError {
+        ParseError { kind, value: None, source: None }
+    }
+}
+
+impl PartialEq for ParseError {
+    fn eq(&self, other: &ParseError) -> bool { self.kind == other.kind }
+}
+
+impl Eq for ParseError {}
+
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        match *self {
-            ParseError::ExpectedType(t) =>
-                write!(f, "Expected response of type {}", t),
-            ParseError::ExpectedFieldType(k, t) =>
-                write!(f, "Expected field {} of type {}", k, t),
-            ParseError::ExpectedFieldValue(k, v) =>
-                write!(f, "Expected field {} to equal {}", k, v),
-            ParseError::UnexpectedField(k) =>
-                write!(f, "Unexpected field {}", k),
+        match self.kind {
+            ParseErrorKind::ExpectedType(ref t) =>
+                write!(f, "Expected response of type {}", t)?,
+            ParseErrorKind::ExpectedFieldType(ref k, ref t) =>
+                write!(f, "Expected field {} of type {}", k, t)?,
+            ParseErrorKind::ExpectedFieldValue(ref k, ref v) =>
+                write!(f, "Expected field {} to equal {}", k, v)?,
+            ParseErrorKind::UnexpectedField(ref k) =>
+                write!(f, "Unexpected field {}", k)?,
         }
+        if let Some(ref value) = self.value {
+            write!(f, ", got {}", value)?;
+        }
+        Ok(())
     }
 }
 
 impl Error for ParseError {
-    fn description(&self) -> &str { "response parse error" }
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.source {
+            Some(ref source) => Some(&**source),
+            None => None,
+        }
+    }
+}
+
+/// Maximum length of values kept in parse errors.
+const VALUE_LIMIT: usize = 64;
+
+fn json_type(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Returns the response as an object.
+pub fn expect_object(json: &Value) -> Result<&Map<String, Value>, ParseError> {
+    json.as_object().ok_or_else(|| ParseError::expected_type("object").with_value(json))
+}
+
+/// Returns a required string field.
+pub fn str_field<'a>(obj: &'a Map<String, Value>, key: &str) -> Result<&'a str, ParseError> {
+    match obj.get(key) {
+        Some(Value::String(s)) => Ok(s),
+        Some(value) =>
+            Err(ParseError::expected_field_type(key.to_owned(), "string").with_value(value)),
+        None => Err(ParseError::expected_field_type(key.to_owned(), "string")),
+    }
+}
+
+/// Decodes the claims of a JWT without verifying it.
+fn jwt_claims(token: &str) -> Option<Map<String, Value>> {
+    let mut parts = token.split('.');
+    let payload = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(_), Some(payload), Some(_), None) => payload,
+        _ => return None,
+    };
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    match serde_json::from_slice(&payload) {
+        Ok(Value::Object(claims)) => Some(claims),
+        _ => None,
+    }
+}
+
+/// Converts a form-encoded response into the JSON structure consumed by `FromResponse`.
+///
+/// Some providers respond to token requests with `application/x-www-form-urlencoded` bodies. Every
+/// field becomes a string, except numeric `expires_in` and `refresh_token_expires_in` fields,
+/// which become numbers. Repeated fields keep their first value.
+pub fn from_form(body: &[u8]) -> Value {
+    let mut obj = Map::new();
+    for (key, value) in form_urlencoded::parse(body) {
+        if obj.contains_key(&key[..]) {
+            continue;
+        }
+        let value = match value.parse::<i64>() {
+            Ok(n) if NUMERIC_FIELDS.contains(&&key[..]) => Value::from(n),
+            _ => Value::from(value.into_owned()),
+        };
+        obj.insert(key.into_owned(), value);
+    }
+    Value::Object(obj)
+}
+
+/// Parses a response body according to its `Content-Type`.
+///
+/// Form-encoded bodies are converted with `from_form`; anything else is parsed as JSON.
+pub(crate) fn parse_body(
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<Value, serde_json::Error> {
+    let media_type = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(str::trim);
+    match media_type {
+        Some(media_type) if media_type.eq_ignore_ascii_case("application/x-www-form-urlencoded") =>
+            Ok(from_form(body)),
+        _ => serde_json::from_slice(body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use serde_json::Value;
+
+    use std::error::Error;
+
+    use test_support::jwt;
+    use super::{from_form, parse_body, str_field, ParseError, ParseErrorKind, ParsePolicy};
+
+    fn obj(json: &str) -> ::serde_json::Map<String, Value> {
+        match json.parse().unwrap() {
+            Value::Object(obj) => obj,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn policy_i64_field() {
+        let obj = obj(r#"{"a":3600,"b":"3600","c":"soon"}"#);
+        let strict = ParsePolicy::strict();
+        assert_eq!(Ok(Some(3600)), strict.i64_field(&obj, "a"));
+        assert_eq!(Err(ParseError::expected_field_type("b", "i64")), strict.i64_field(&obj, "b"));
+        assert_eq!(Ok(None), strict.i64_field(&obj, "d"));
+
+        let lenient = ParsePolicy::lenient();
+        assert_eq!(Ok(Some(3600)), lenient.i64_field(&obj, "b"));
+        assert_eq!(Err(ParseError::expected_field_type("c", "i64")), lenient.i64_field(&obj, "c"));
+    }
+
+    #[test]
+    fn policy_token_type() {
+        let strict = ParsePolicy::strict();
+        let lenient = ParsePolicy::lenient();
+        assert_eq!(Ok(()), strict.check_token_type(&obj(r#"{"token_type":"bearer"}"#), "Bearer"));
+        assert_eq!(
+            Err(ParseError::expected_field_value("token_type", "Bearer")),
+            strict.check_token_type(&obj(r#"{"token_type":"BEARER"}"#), "Bearer")
+        );
+        assert_eq!(Ok(()), lenient.check_token_type(&obj(r#"{"token_type":"BEARER"}"#), "Bearer"));
+        assert_eq!(
+            Err(ParseError::expected_field_type("token_type", "string")),
+            lenient.check_token_type(&obj("{}"), "Bearer")
+        );
+    }
+
+    #[test]
+    fn policy_expiry() {
+        let obj = obj(r#"{"expires_in":3600}"#);
+        let (issued, expires) = ParsePolicy::strict().expiry(&obj).unwrap().unwrap();
+        assert_eq!(Duration::seconds(3600), expires - issued);
+        assert_eq!(Ok(None), ParsePolicy::strict().expiry(&::serde_json::Map::new()));
+    }
+
+    #[test]
+    fn policy_jwt_expiry() {
+        let json = json!({
+            "access_token": jwt(r#"{"sub":"foo","iat":1700000000,"exp":1700003600}"#),
+        });
+        let obj = json.as_object().unwrap();
+        assert_eq!(Ok(None), ParsePolicy::strict().expiry(obj));
+
+        let policy = ParsePolicy::strict().with_jwt_expiry(true);
+        assert!(policy.jwt_expiry());
+        assert_eq!(
+            Ok(Some((
+                Utc.timestamp_opt(1700000000, 0).unwrap(),
+                Utc.timestamp_opt(1700003600, 0).unwrap(),
+            ))),
+            policy.expiry(obj)
+        );
+
+        let json = json!({"access_token": jwt(r#"{"exp":1700003600}"#), "expires_in": 60});
+        let (issued, expires) = policy.expiry(json.as_object().unwrap()).unwrap().unwrap();
+        assert_eq!(Duration::seconds(60), expires - issued);
+    }
+
+    #[test]
+    fn policy_jwt_expiry_invalid() {
+        let policy = ParsePolicy::lenient().with_jwt_expiry(true);
+        for access_token in &[
+            String::from("aaaaaaaa"),
+            String::from("a.b.c"),
+            jwt(r#"{"sub":"foo"}"#),
+            jwt(r#"{"exp":"tomorrow"}"#),
+            jwt("[]"),
+        ] {
+            let json = json!({ "access_token": access_token });
+            assert_eq!(Ok(None), policy.expiry(json.as_object().unwrap()));
+        }
+    }
+
+    #[test]
+    fn policy_unexpected() {
+        let obj = obj(r#"{"refresh_token":"aaaaaaaa"}"#);
+        assert_eq!(
+            Err(ParseError::unexpected_field("refresh_token")),
+            ParsePolicy::strict().check_unexpected(&obj, "refresh_token")
+        );
+        assert_eq!(Ok(()), ParsePolicy::lenient().check_unexpected(&obj, "refresh_token"));
+        assert_eq!(Ok(()), ParsePolicy::strict().check_unexpected(&obj, "expires_in"));
+    }
+
+    #[test]
+    fn form() {
+        let json = from_form(
+            b"access_token=aaaaaaaa&token_type=bearer&expires_in=3600&scope=repo%2Cgist"
+        );
+        assert_eq!(
+            json!({
+                "access_token": "aaaaaaaa",
+                "token_type": "bearer",
+                "expires_in": 3600,
+                "scope": "repo,gist",
+            }),
+            json
+        );
+    }
+
+    #[test]
+    fn form_non_numeric_fields() {
+        let json = from_form(b"access_token=1234&expires_in=soon&expires_in=60");
+        assert_eq!(json!({"access_token": "1234", "expires_in": "soon"}), json);
+    }
+
+    #[test]
+    fn body_content_type() {
+        let form = "application/x-www-form-urlencoded; charset=utf-8";
+        assert_eq!(
+            json!({"error": "bad_verification_code"}),
+            parse_body(Some(form), b"error=bad_verification_code").unwrap()
+        );
+        let json = br#"{"foo":1}"#;
+        assert_eq!(json!({"foo": 1}), parse_body(Some("application/json"), json).unwrap());
+        assert_eq!(json!({"foo": 1}), parse_body(None, json).unwrap());
+        assert!(parse_body(Some("text/html"), b"foo=bar").is_err());
+    }
+
+    #[test]
+    fn parse_error_context() {
+        let obj = obj(r#"{"expires_in":"soon","access_token":1234,"custom_token":[]}"#);
+
+        let err = ParsePolicy::lenient().i64_field(&obj, "expires_in").unwrap_err();
+        assert_eq!(Some("expires_in"), err.field());
+        assert_eq!(Some(r#""soon""#), err.value());
+        assert!(err.source().is_some());
+        assert_eq!(r#"Expected field expires_in of type i64, got "soon""#, err.to_string());
+
+        let err = str_field(&obj, "access_token").unwrap_err();
+        assert_eq!(
+            &ParseErrorKind::ExpectedFieldType("access_token".into(), "string".into()),
+            err.kind()
+        );
+        assert!(err.value().unwrap().starts_with("[redacted sha256:"));
+        assert!(!err.to_string().contains("1234"));
+
+        let field = String::from("custom_token");
+        let err = ParseError::expected_field_type(field, "string").with_value(&obj["custom_token"]);
+        assert!(err.value().unwrap().starts_with("[redacted"));
+
+        let err = ParseError::expected_type("object").with_value(&json!(["aaaaaaaa"]));
+        assert_eq!(Some("array"), err.value());
+    }
 }
@@ -0,0 +1,217 @@
+//! Retrying token requests.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hyper;
+use rand_core::{OsRng, RngCore};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{self, StatusCode};
+
+use client::{ClientError, ClientErrorKind};
+use error::OAuth2ErrorCode;
+
+/// Policy for retrying token requests which failed transiently.
+///
+/// Token requests are not idempotent: an authorization code may only be used once, and a refresh
+/// token may be rotated, after which replaying it fails with `invalid_grant` or revokes the whole
+/// token family. Requests are therefore only retried when the server cannot have processed them.
+///
+/// Authorization code requests are only retried after connection errors, since any response means
+/// the server received the code. Refresh requests are also retried after `429 Too Many Requests`
+/// and `503 Service Unavailable` responses, and `temporarily_unavailable` errors, which reject the
+/// request before processing it. Timeouts, other `5xx` responses and `server_error` errors are
+/// deliberately not retried, as the server may have rotated the refresh token before failing.
+///
+/// Retries are delayed by an exponential backoff with jitter, or by the `Retry-After` header of
+/// the response if it is longer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries.
+    pub max_retries: u32,
+
+    /// Backoff before the first retry, doubled for every further retry.
+    pub initial_backoff: Duration,
+
+    /// Maximum backoff.
+    ///
+    /// Requests are not retried if `Retry-After` asks for a longer delay.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Retries 3 times, backing off from 500 milliseconds up to 30 seconds.
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a policy retrying up to a number of times, with the default backoff.
+    pub fn new(max_retries: u32) -> Self {
+        RetryPolicy { max_retries, ..RetryPolicy::default() }
+    }
+
+    /// Sets the initial and maximum backoff.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Returns the delay before a retry, or `None` if the request should not be retried.
+    pub(crate) fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if retry >= self.max_retries {
+            return None;
+        }
+        if retry_after.is_some_and(|retry_after| retry_after > self.max_backoff) {
+            return None;
+        }
+
+        let backoff = self.initial_backoff
+            .checked_mul(1 << retry.min(31))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff));
+
+        // Equal jitter: half the backoff, plus a random part of the other half.
+        let half = backoff / 2;
+        let jitter = half.mul_f64(f64::from(OsRng.next_u32()) / f64::from(u32::MAX));
+        let delay = half + jitter;
+
+        Some(retry_after.map_or(delay, |retry_after| delay.max(retry_after)))
+    }
+}
+
+/// Returns true if a failed request may be retried, since the server cannot have processed it.
+///
+/// Unless `responses` is set, only requests which never reached the server are retried.
+pub(crate) fn is_retryable(err: &ClientError, responses: bool) -> bool {
+    match *err.kind() {
+        ClientErrorKind::Reqwest(ref err) => is_connect(err),
+        ClientErrorKind::Http(ref raw) if responses => matches!(
+            raw.status,
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+        ),
+        ClientErrorKind::OAuth2(ref err) if responses =>
+            err.code == OAuth2ErrorCode::TemporarilyUnavailable,
+        _ => false,
+    }
+}
+
+/// Parses the `Retry-After` header, in either delay-seconds or HTTP-date form.
+///
+/// See [RFC 7231, section 7.1.3](https://tools.ietf.org/html/rfc7231#section-7.1.3).
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Returns true if the connection to the server could not be established.
+fn is_connect(err: &reqwest::Error) -> bool {
+    err.get_ref()
+        .and_then(|err| err.downcast_ref::<hyper::Error>())
+        .is_some_and(hyper::Error::is_connect)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::{self, StatusCode};
+
+    use client::{ClientError, ClientErrorKind, RawResponse};
+    use error::{OAuth2Error, OAuth2ErrorCode};
+    use super::{is_retryable, retry_after, RetryPolicy};
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new(3).with_backoff(Duration::from_millis(100), Duration::from_millis(300))
+    }
+
+    #[test]
+    fn delay() {
+        let policy = policy();
+        for _ in 0..20 {
+            let delay = policy.delay(0, None).unwrap();
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+            let delay = policy.delay(1, None).unwrap();
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(200));
+            let delay = policy.delay(2, None).unwrap();
+            assert!(delay >= Duration::from_millis(150) && delay <= Duration::from_millis(300));
+        }
+        assert_eq!(None, policy.delay(3, None));
+    }
+
+    #[test]
+    fn delay_retry_after() {
+        let policy = policy();
+        assert_eq!(
+            Some(Duration::from_millis(250)),
+            policy.delay(0, Some(Duration::from_millis(250)))
+        );
+        assert_eq!(None, policy.delay(0, Some(Duration::from_secs(1))));
+    }
+
+    #[test]
+    fn parse_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, retry_after(&headers));
+
+        headers.insert("Retry-After", HeaderValue::from_static("120"));
+        assert_eq!(Some(Duration::from_secs(120)), retry_after(&headers));
+
+        let date = (Utc::now() + ::chrono::Duration::seconds(60)).to_rfc2822();
+        headers.insert("Retry-After", HeaderValue::from_str(&date).unwrap());
+        let delay = retry_after(&headers).unwrap();
+        assert!(delay > Duration::from_secs(55) && delay <= Duration::from_secs(60));
+
+        headers.insert("Retry-After", HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"));
+        assert_eq!(Some(Duration::from_secs(0)), retry_after(&headers));
+    }
+
+    #[test]
+    fn retryable_connect_error() {
+        let err = reqwest::Client::new().post("http://127.0.0.1:1/token").send().unwrap_err();
+        let err = ClientError::from(err);
+        assert!(is_retryable(&err, true));
+        assert!(is_retryable(&err, false));
+    }
+
+    #[test]
+    fn retryable_status() {
+        let raw = |status| {
+            ClientError::from(ClientErrorKind::Http(
+                Box::new(RawResponse::new(status, &HeaderMap::new(), b""))
+            ))
+        };
+        assert!(is_retryable(&raw(StatusCode::SERVICE_UNAVAILABLE), true));
+        assert!(is_retryable(&raw(StatusCode::TOO_MANY_REQUESTS), true));
+        assert!(!is_retryable(&raw(StatusCode::BAD_GATEWAY), true));
+        assert!(!is_retryable(&raw(StatusCode::INTERNAL_SERVER_ERROR), true));
+        assert!(!is_retryable(&raw(StatusCode::NOT_FOUND), true));
+        assert!(!is_retryable(&raw(StatusCode::SERVICE_UNAVAILABLE), false));
+    }
+
+    #[test]
+    fn retryable_oauth2_error() {
+        let oauth2 = |code| ClientError::from(OAuth2Error {
+            code,
+            description: None,
+            uri: None,
+            operation: None,
+            endpoint: None,
+        });
+        assert!(is_retryable(&oauth2(OAuth2ErrorCode::TemporarilyUnavailable), true));
+        assert!(!is_retryable(&oauth2(OAuth2ErrorCode::ServerError), true));
+        assert!(!is_retryable(&oauth2(OAuth2ErrorCode::InvalidGrant), true));
+        assert!(!is_retryable(&oauth2(OAuth2ErrorCode::TemporarilyUnavailable), false));
+    }
+}
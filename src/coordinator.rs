@@ -0,0 +1,279 @@
+//! Cross-process refresh coordination.
+
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, Utc};
+use fs2::FileExt;
+use reqwest;
+
+use client::{Client, ClientError};
+use provider::Provider;
+use store::TokenStore;
+use token::{Lifetime, Refreshable, Token};
+
+/// Coordinates token refreshes between processes sharing a stored token.
+///
+/// When several processes share a persisted refresh token, they may all find the access token
+/// expired at once. With rotating refresh tokens, all but the first refresh then fail with
+/// `invalid_grant`. The coordinator serializes refreshes with an advisory lock on a file: once the
+/// lock is held, the stored token is reloaded and only refreshed if it still needs to be, so the
+/// other processes pick up the token saved by the first.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate inth_oauth2;
+/// # extern crate reqwest;
+/// # use inth_oauth2::Client;
+/// # use inth_oauth2::provider::google::Installed;
+/// use inth_oauth2::coordinator::RefreshCoordinator;
+/// use inth_oauth2::store::FileStore;
+///
+/// # fn main() {
+/// # let client = Client::new(Installed, String::new(), String::new(), None);
+/// # let http = reqwest::Client::new();
+/// let coordinator = RefreshCoordinator::new(
+///     client,
+///     http,
+///     FileStore::new("/home/user/.config/example/tokens"),
+///     "user@example.com",
+///     "/home/user/.config/example/tokens/user@example.com.lock",
+/// );
+/// let token = coordinator.token().unwrap().expect("not authorized");
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RefreshCoordinator<P, S> {
+    client: Client<P>,
+    http_client: reqwest::Client,
+    store: S,
+    account: String,
+    lock_path: PathBuf,
+    margin: Duration,
+}
+
+impl<P, S> RefreshCoordinator<P, S>
+where P: Provider, P::Lifetime: Refreshable, S: TokenStore<P::Token> {
+    /// Creates a coordinator for the token stored under an account name, locking a file at a path.
+    ///
+    /// Tokens are refreshed when they are within 60 seconds of expiring.
+    pub fn new<L: Into<PathBuf>>(
+        client: Client<P>,
+        http_client: reqwest::Client,
+        store: S,
+        account: &str,
+        lock_path: L,
+    ) -> Self {
+        RefreshCoordinator {
+            client,
+            http_client,
+            store,
+            account: account.to_owned(),
+            lock_path: lock_path.into(),
+            margin: Duration::seconds(60),
+        }
+    }
+
+    /// Sets how long before expiry tokens are refreshed.
+    pub fn with_margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Returns the store.
+    pub fn store(&self) -> &S { &self.store }
+
+    /// Returns the path of the lock file.
+    pub fn lock_path(&self) -> &Path { &self.lock_path }
+
+    /// Returns a valid stored token, refreshing it if necessary.
+    ///
+    /// Returns `None` if no token is stored for the account.
+    ///
+    /// If the refreshed token cannot be saved, returns `ClientErrorKind::UnsavedToken` carrying
+    /// it, since the stored refresh token may no longer be valid.
+    pub fn token(&self) -> Result<Option<P::Token>, ClientError>
+    where P::Token: Send + Sync + 'static {
+        let token = match self.store.load(&self.account)? {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        if !self.needs_refresh(&token) {
+            return Ok(Some(token));
+        }
+
+        let lock = self.lock()?;
+
+        // Another process may have refreshed the token while we waited.
+        let token = match self.store.load(&self.account)? {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        if !self.needs_refresh(&token) {
+            return Ok(Some(token));
+        }
+
+        let token = self.client.refresh(&self.http_client, &token, None)?.token;
+        if let Err(err) = self.store.save(&self.account, &token) {
+            return Err(ClientError::unsaved(token, err));
+        }
+
+        // Dropping the file would also release the lock, including on the error paths above.
+        lock.unlock()?;
+        Ok(Some(token))
+    }
+
+    fn needs_refresh(&self, token: &P::Token) -> bool {
+        token.lifetime().expires_at().is_some_and(|expires| expires - self.margin <= Utc::now())
+    }
+
+    fn lock(&self) -> Result<File, ClientError> {
+        let file = open_lock(&self.lock_path)?;
+        file.lock_exclusive()?;
+        Ok(file)
+    }
+}
+
+#[cfg(unix)]
+fn open_lock(path: &Path) -> ::std::io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new().read(true).write(true).create(true).truncate(false).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn open_lock(path: &Path) -> ::std::io::Result<File> {
+    OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time;
+
+    use reqwest;
+    use url::Url;
+
+    use client::{Client, ClientErrorKind};
+    use store::{FileStore, MemoryStore, TokenStore};
+    use test_support::{
+        token_with_refresh, ReadOnlyStore, Request, Response, Server, TempDir, TestProvider,
+    };
+    use token::{Bearer, Refresh, Token};
+    use super::RefreshCoordinator;
+
+    const TOKEN_URI: &str = "INTH_OAUTH2_TEST_TOKEN_URI";
+    const STORE_DIR: &str = "INTH_OAUTH2_TEST_STORE_DIR";
+
+    type Test = TestProvider<Bearer<Refresh>, Refresh>;
+
+    fn coordinator(token_uri: Url, dir: &Path) -> RefreshCoordinator<Test, FileStore> {
+        let provider = Test::new(token_uri.clone(), token_uri);
+        let client = Client::new(provider, String::from("foo"), String::from("bar"), None);
+        let store = FileStore::new(dir);
+        let lock_path = store.path("foo").with_extension("lock");
+        RefreshCoordinator::new(client, reqwest::Client::new(), store, "foo", lock_path)
+    }
+
+    /// Token endpoint rotating the refresh token on every request.
+    fn rotating_server() -> Server {
+        let current = Mutex::new(0);
+        Server::new(move |_, request: &Request| {
+            thread::sleep(time::Duration::from_millis(100));
+            let mut current = current.lock().unwrap();
+            let expected = format!("refresh_token=rt{}", *current);
+            if !request.body.split('&').any(|pair| pair == expected) {
+                return Response::json(400, r#"{"error":"invalid_grant"}"#);
+            }
+            *current += 1;
+            Response::json(200, &format!(
+                r#"{{"token_type":"Bearer","access_token":"at{0}","expires_in":3600,"refresh_token":"rt{0}"}}"#,
+                *current,
+            ))
+        })
+    }
+
+    /// Refreshes the shared token when run as a child process of `concurrent_processes`.
+    #[test]
+    #[ignore = "run as a child process by concurrent_processes"]
+    fn child_refresh() {
+        let token_uri = env::var(TOKEN_URI).expect("token endpoint not set");
+        let dir = env::var(STORE_DIR).expect("store directory not set");
+        let coordinator = coordinator(Url::parse(&token_uri).unwrap(), Path::new(&dir));
+        let token = coordinator.token().unwrap().unwrap();
+        assert_eq!("at1", token.access_token());
+    }
+
+    #[test]
+    fn concurrent_processes() {
+        let server = rotating_server();
+        let dir = TempDir::new("coordinator");
+
+        let token: Bearer<Refresh> = token_with_refresh("Bearer", "at0", "rt0", 0);
+        FileStore::new(dir.path()).save("foo", &token).unwrap();
+
+        let children: Vec<_> = (0..4).map(|_| {
+            Command::new(env::current_exe().unwrap())
+                .args(["--exact", "--quiet", "--ignored", "coordinator::tests::child_refresh"])
+                .env(TOKEN_URI, server.url("/token").as_str())
+                .env(STORE_DIR, dir.path())
+                .spawn()
+                .unwrap()
+        }).collect();
+
+        for mut child in children {
+            assert!(child.wait().unwrap().success());
+        }
+        assert_eq!(1, server.hits());
+
+        let token: Bearer<Refresh> = FileStore::new(dir.path()).load("foo").unwrap().unwrap();
+        assert_eq!("rt1", token.lifetime().refresh_token());
+    }
+
+    #[test]
+    fn token_valid() {
+        let server = rotating_server();
+        let dir = TempDir::new("coordinator-valid");
+        let coordinator = coordinator(server.url("/token"), dir.path());
+        assert!(coordinator.token().unwrap().is_none());
+
+        let token: Bearer<Refresh> = token_with_refresh("Bearer", "at0", "rt0", 3600);
+        coordinator.store().save("foo", &token).unwrap();
+
+        assert_eq!("at0", coordinator.token().unwrap().unwrap().access_token());
+        assert_eq!(0, server.hits());
+    }
+
+    #[test]
+    fn token_unsaved() {
+        let server = rotating_server();
+        let dir = TempDir::new("coordinator-unsaved");
+        fs::create_dir_all(dir.path()).unwrap();
+
+        let token: Bearer<Refresh> = token_with_refresh("Bearer", "at0", "rt0", 0);
+        let store = ReadOnlyStore(MemoryStore::new());
+        store.0.save("foo", &token).unwrap();
+
+        let client = Client::new(Test::on(&server), String::from("foo"), String::from("bar"), None);
+        let lock_path = dir.path().join("foo.lock");
+        let coordinator =
+            RefreshCoordinator::new(client, reqwest::Client::new(), store, "foo", lock_path);
+
+        let err = coordinator.token().unwrap_err();
+        let token = err.unsaved_token::<Bearer<Refresh>>().unwrap();
+        assert_eq!("at1", token.access_token());
+        assert_eq!("rt1", token.lifetime().refresh_token());
+        match err.into_kind() {
+            ClientErrorKind::UnsavedToken(_, ref err) => match *err.kind() {
+                ClientErrorKind::Io(_) => {},
+                ref kind => panic!("unexpected error {:?}", kind),
+            },
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Demonstrating Proof of Possession (DPoP).
+//!
+//! DPoP binds access tokens to a key pair held by the client. Every token request and resource
+//! request carries a proof JWT signed with the private key, so a stolen access token cannot be
+//! used without it.
+//!
+//! See [RFC 9449](https://www.rfc-editor.org/rfc/rfc9449).
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use rand_core::{OsRng, RngCore};
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// The `DPoP` request header, carrying a proof.
+pub const DPOP: &str = "DPoP";
+
+/// The `DPoP-Nonce` response header, carrying a server-provided nonce.
+pub const DPOP_NONCE: &str = "DPoP-Nonce";
+
+/// An ephemeral key pair used to sign DPoP proofs.
+///
+/// Keys are generated in memory and never persisted, so tokens bound to a key cannot outlive the
+/// process. The key also remembers the most recent nonce provided by each server.
+pub struct DPoPKey {
+    signing_key: SigningKey,
+    jwk: String,
+    nonces: Mutex<HashMap<String, String>>,
+}
+
+impl DPoPKey {
+    /// Generates a P-256 key pair for ES256 proofs.
+    pub fn generate() -> Self {
+        let signing_key = SigningKey::random(&mut OsRng);
+        let point = signing_key.verifying_key().to_encoded_point(false);
+        let coordinate = |bytes: Option<&[u8]>| URL_SAFE_NO_PAD.encode(bytes.unwrap_or(&[]));
+
+        // Members in lexicographic order, as required for the thumbprint.
+        let jwk = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            coordinate(point.x().map(|x| &x[..])),
+            coordinate(point.y().map(|y| &y[..])),
+        );
+
+        DPoPKey {
+            signing_key,
+            jwk,
+            nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the public key as a JSON Web Key.
+    pub fn jwk(&self) -> &str { &self.jwk }
+
+    /// Returns the JWK SHA-256 thumbprint of the public key.
+    ///
+    /// This is the value of the `dpop_jkt` authorization request parameter.
+    ///
+    /// See [RFC 7638](https://www.rfc-editor.org/rfc/rfc7638).
+    pub fn thumbprint(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.jwk.as_bytes()))
+    }
+
+    /// Creates a proof for a request.
+    ///
+    /// Resource requests must pass the access token, whose hash is included in the proof. The
+    /// most recent nonce recorded for the server is included, if any.
+    ///
+    /// See [RFC 9449, section 4](https://www.rfc-editor.org/rfc/rfc9449#section-4).
+    pub fn proof(&self, method: &Method, url: &Url, access_token: Option<&str>) -> String {
+        let mut htu = url.clone();
+        htu.set_query(None);
+        htu.set_fragment(None);
+
+        let mut jti = [0; 16];
+        OsRng.fill_bytes(&mut jti);
+
+        let header = format!(r#"{{"typ":"dpop+jwt","alg":"ES256","jwk":{}}}"#, self.jwk);
+
+        let mut claims = json!({
+            "jti": URL_SAFE_NO_PAD.encode(jti),
+            "htm": method.as_str(),
+            "htu": htu.as_str(),
+            "iat": Utc::now().timestamp(),
+        });
+        if let Some(access_token) = access_token {
+            claims["ath"] = URL_SAFE_NO_PAD.encode(Sha256::digest(access_token.as_bytes())).into();
+        }
+        if let Some(nonce) = self.nonce(url) {
+            claims["nonce"] = nonce.into();
+        }
+
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.as_bytes()),
+            URL_SAFE_NO_PAD.encode(claims.to_string().as_bytes()),
+        );
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+        format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.to_bytes()))
+    }
+
+    /// Returns the most recent nonce recorded for a server.
+    pub fn nonce(&self, url: &Url) -> Option<String> {
+        self.lock().get(&origin(url)).cloned()
+    }
+
+    /// Records the nonce provided in a server's response headers, if any.
+    ///
+    /// Returns true if a new nonce was recorded, differing from the previous one for the server.
+    ///
+    /// See [RFC 9449, section 8](https://www.rfc-editor.org/rfc/rfc9449#section-8).
+    pub fn record_nonce(&self, url: &Url, headers: &HeaderMap) -> bool {
+        match headers.get(DPOP_NONCE).and_then(|value| value.to_str().ok()) {
+            Some(nonce) => {
+                let previous = self.lock().insert(origin(url), nonce.to_owned());
+                previous.as_ref().map(|previous| &previous[..]) != Some(nonce)
+            },
+            None => false,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, String>> {
+        self.nonces.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for DPoPKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("DPoPKey")
+            .field("thumbprint", &self.thumbprint())
+            .finish()
+    }
+}
+
+impl PartialEq for DPoPKey {
+    fn eq(&self, other: &DPoPKey) -> bool {
+        self.jwk == other.jwk
+    }
+}
+
+impl Eq for DPoPKey {}
+
+fn origin(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use p256::ecdsa::signature::Verifier;
+    use p256::ecdsa::{Signature, VerifyingKey};
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::Method;
+    use serde_json::{self, Value};
+    use url::Url;
+
+    use super::DPoPKey;
+
+    fn decode(proof: &str) -> (Value, Value) {
+        let parts: Vec<_> = proof.split('.').collect();
+        assert_eq!(3, parts.len());
+        let header = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[0]).unwrap()).unwrap();
+        let claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(parts[1]).unwrap()).unwrap();
+        (header, claims)
+    }
+
+    #[test]
+    fn proof() {
+        let key = DPoPKey::generate();
+        let url = Url::parse("https://example.com/token?foo=bar#baz").unwrap();
+        let proof = key.proof(&Method::POST, &url, None);
+        let (header, claims) = decode(&proof);
+
+        assert_eq!("dpop+jwt", header["typ"]);
+        assert_eq!("ES256", header["alg"]);
+        assert_eq!("EC", header["jwk"]["kty"]);
+        assert_eq!("POST", claims["htm"]);
+        assert_eq!("https://example.com/token", claims["htu"]);
+        assert!(claims["iat"].is_i64());
+        assert!(claims["jti"].is_string());
+        assert!(claims.get("ath").is_none());
+        assert!(claims.get("nonce").is_none());
+
+        let jti = claims["jti"].clone();
+        let (_, claims) = decode(&key.proof(&Method::POST, &url, None));
+        assert_ne!(jti, claims["jti"]);
+    }
+
+    #[test]
+    fn proof_signature() {
+        let key = DPoPKey::generate();
+        let url = Url::parse("https://example.com/resource").unwrap();
+        let proof = key.proof(&Method::GET, &url, None);
+
+        let (input, signature) = proof.split_at(proof.rfind('.').unwrap());
+        let signature = URL_SAFE_NO_PAD.decode(&signature[1..]).unwrap();
+        let signature = Signature::from_slice(&signature).unwrap();
+        let verifying_key = VerifyingKey::from(&key.signing_key);
+        assert!(verifying_key.verify(input.as_bytes(), &signature).is_ok());
+    }
+
+    #[test]
+    fn proof_access_token_hash() {
+        let key = DPoPKey::generate();
+        let url = Url::parse("https://example.com/resource").unwrap();
+        let access_token = "Kz~8mXK1EalYznwH-LC-1fBAo.4Ljp~zsPE_NeO.gxU";
+        let (_, claims) = decode(&key.proof(&Method::GET, &url, Some(access_token)));
+        // Example from RFC 9449, section 7.1.
+        assert_eq!("fUHyO2r2Z3DZ53EsNrWBb0xWXoaNy59IiKCAqksmQEo", claims["ath"]);
+    }
+
+    #[test]
+    fn proof_nonce() {
+        let key = DPoPKey::generate();
+        let url = Url::parse("https://example.com/token").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("DPoP-Nonce", HeaderValue::from_static("eyJ7S_zG.eyJH0-Z.HX4w-7v"));
+        assert!(key.record_nonce(&url, &headers));
+        assert!(!key.record_nonce(&url, &headers));
+
+        let (_, claims) = decode(&key.proof(&Method::POST, &url, None));
+        assert_eq!("eyJ7S_zG.eyJH0-Z.HX4w-7v", claims["nonce"]);
+
+        let other = Url::parse("https://resource.example.com/").unwrap();
+        let (_, claims) = decode(&key.proof(&Method::GET, &other, None));
+        assert!(claims.get("nonce").is_none());
+    }
+
+    #[test]
+    fn thumbprint() {
+        let key = DPoPKey::generate();
+        assert_eq!(43, key.thumbprint().len());
+        assert_ne!(key.thumbprint(), DPoPKey::generate().thumbprint());
+    }
+}
@@ -3,13 +3,16 @@
 use std::error::Error;
 use std::fmt;
 
+use reqwest::header::{HeaderMap, WWW_AUTHENTICATE};
 use serde_json::Value;
+use url::Url;
 
-use client::response::{FromResponse, ParseError};
+use client::response::{expect_object, str_field, FromResponse, ParseError};
 
 /// OAuth 2.0 error codes.
 ///
-/// See [RFC 6749, section 5.2](http://tools.ietf.org/html/rfc6749#section-5.2).
+/// See [RFC 6749, section 5.2](http://tools.ietf.org/html/rfc6749#section-5.2) and the
+/// [OAuth Extensions Error registry](https://www.iana.org/assignments/oauth-parameters/oauth-parameters.xhtml#extensions-error).
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OAuth2ErrorCode {
     /// The request is missing a required parameter, includes an unsupported parameter value (other
@@ -36,11 +39,144 @@ pub enum OAuth2ErrorCode {
     /// resource owner.
     InvalidScope,
 
-    /// An unrecognized error code, not defined in RFC 6749.
+    /// The authorization server encountered an unexpected condition which prevented it from
+    /// fulfilling the request.
+    ///
+    /// See [RFC 6749, section 4.1.2.1](http://tools.ietf.org/html/rfc6749#section-4.1.2.1).
+    ServerError,
+
+    /// The authorization server is currently unable to handle the request due to a temporary
+    /// overloading or maintenance of the server.
+    ///
+    /// See [RFC 6749, section 4.1.2.1](http://tools.ietf.org/html/rfc6749#section-4.1.2.1).
+    TemporarilyUnavailable,
+
+    /// The resource owner or authorization server denied the request.
+    ///
+    /// See [RFC 6749, section 4.1.2.1](http://tools.ietf.org/html/rfc6749#section-4.1.2.1) and
+    /// [RFC 8628, section 3.5](https://www.rfc-editor.org/rfc/rfc8628#section-3.5).
+    AccessDenied,
+
+    /// The user has not yet completed device authorization; poll again after the interval.
+    ///
+    /// See [RFC 8628, section 3.5](https://www.rfc-editor.org/rfc/rfc8628#section-3.5).
+    AuthorizationPending,
+
+    /// Device authorization is still pending, and the polling interval must be increased by 5
+    /// seconds.
+    ///
+    /// See [RFC 8628, section 3.5](https://www.rfc-editor.org/rfc/rfc8628#section-3.5).
+    SlowDown,
+
+    /// The device code has expired, and a new device authorization request must be made.
+    ///
+    /// See [RFC 8628, section 3.5](https://www.rfc-editor.org/rfc/rfc8628#section-3.5).
+    ExpiredToken,
+
+    /// The access token provided is expired, revoked, malformed, or invalid for other reasons.
+    ///
+    /// See [RFC 6750, section 3.1](http://tools.ietf.org/html/rfc6750#section-3.1).
+    InvalidToken,
+
+    /// The request requires higher privileges than provided by the access token.
+    ///
+    /// See [RFC 6750, section 3.1](http://tools.ietf.org/html/rfc6750#section-3.1).
+    InsufficientScope,
+
+    /// The DPoP proof is invalid.
+    ///
+    /// See [RFC 9449, section 5](https://www.rfc-editor.org/rfc/rfc9449#section-5) and [section
+    /// 7.1](https://www.rfc-editor.org/rfc/rfc9449#section-7.1).
+    InvalidDPoPProof,
+
+    /// The server requires a nonce in the DPoP proof.
+    ///
+    /// See [RFC 9449, section 8](https://www.rfc-editor.org/rfc/rfc9449#section-8) and [section
+    /// 9](https://www.rfc-editor.org/rfc/rfc9449#section-9).
+    UseDPoPNonce,
+
+    /// The requested resource is invalid, unknown, or malformed.
+    ///
+    /// See [RFC 8707, section 2](https://www.rfc-editor.org/rfc/rfc8707#section-2).
+    InvalidTarget,
+
+    /// An unrecognized error code, not registered.
     Unrecognized(String),
 }
 
-impl<'a> From<&'a str> for OAuth2ErrorCode {
+impl OAuth2ErrorCode {
+    /// Returns the registered name of the error code.
+    pub fn as_str(&self) -> &str {
+        match *self {
+            OAuth2ErrorCode::InvalidRequest => "invalid_request",
+            OAuth2ErrorCode::InvalidClient => "invalid_client",
+            OAuth2ErrorCode::InvalidGrant => "invalid_grant",
+            OAuth2ErrorCode::UnauthorizedClient => "unauthorized_client",
+            OAuth2ErrorCode::UnsupportedGrantType => "unsupported_grant_type",
+            OAuth2ErrorCode::InvalidScope => "invalid_scope",
+            OAuth2ErrorCode::ServerError => "server_error",
+            OAuth2ErrorCode::TemporarilyUnavailable => "temporarily_unavailable",
+            OAuth2ErrorCode::AccessDenied => "access_denied",
+            OAuth2ErrorCode::AuthorizationPending => "authorization_pending",
+            OAuth2ErrorCode::SlowDown => "slow_down",
+            OAuth2ErrorCode::ExpiredToken => "expired_token",
+            OAuth2ErrorCode::InvalidToken => "invalid_token",
+            OAuth2ErrorCode::InsufficientScope => "insufficient_scope",
+            OAuth2ErrorCode::InvalidDPoPProof => "invalid_dpop_proof",
+            OAuth2ErrorCode::UseDPoPNonce => "use_dpop_nonce",
+            OAuth2ErrorCode::InvalidTarget => "invalid_target",
+            OAuth2ErrorCode::Unrecognized(ref s) => s,
+        }
+    }
+
+    /// Returns true if the same request may succeed if repeated.
+    ///
+    /// The server may be temporarily unavailable, device authorization may still be pending, or a
+    /// DPoP proof may need the nonce the server provided. Callers should wait before retrying, as
+    /// directed by `Retry-After` or the polling interval.
+    ///
+    /// `server_error` is not retryable, since the server may have processed the request before
+    /// failing, consuming an authorization code or rotating a refresh token. Of these codes,
+    /// `client::RetryPolicy` only retries `temporarily_unavailable`: the others call for a polling
+    /// interval or a nonce, which the caller or the client handles.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            *self,
+            OAuth2ErrorCode::TemporarilyUnavailable
+                | OAuth2ErrorCode::AuthorizationPending
+                | OAuth2ErrorCode::SlowDown
+                | OAuth2ErrorCode::UseDPoPNonce
+        )
+    }
+
+    /// Returns true if the access token should be refreshed before retrying.
+    pub fn requires_refresh(&self) -> bool {
+        *self == OAuth2ErrorCode::InvalidToken
+    }
+
+    /// Returns true if the user must authorize the client again, restarting the authorization
+    /// flow.
+    ///
+    /// The grant or refresh token may have been revoked or expired, the user may have denied
+    /// access, or additional scope may be needed.
+    pub fn requires_reauthorization(&self) -> bool {
+        matches!(
+            *self,
+            OAuth2ErrorCode::InvalidGrant
+                | OAuth2ErrorCode::AccessDenied
+                | OAuth2ErrorCode::ExpiredToken
+                | OAuth2ErrorCode::InsufficientScope
+        )
+    }
+}
+
+impl fmt::Display for OAuth2ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for OAuth2ErrorCode {
     fn from(s: &str) -> OAuth2ErrorCode {
         match s {
             "invalid_request" => OAuth2ErrorCode::InvalidRequest,
@@ -49,11 +185,57 @@ impl<'a> From<&'a str> for OAuth2ErrorCode {
             "unauthorized_client" => OAuth2ErrorCode::UnauthorizedClient,
             "unsupported_grant_type" => OAuth2ErrorCode::UnsupportedGrantType,
             "invalid_scope" => OAuth2ErrorCode::InvalidScope,
+            "server_error" => OAuth2ErrorCode::ServerError,
+            "temporarily_unavailable" => OAuth2ErrorCode::TemporarilyUnavailable,
+            "access_denied" => OAuth2ErrorCode::AccessDenied,
+            "authorization_pending" => OAuth2ErrorCode::AuthorizationPending,
+            "slow_down" => OAuth2ErrorCode::SlowDown,
+            "expired_token" => OAuth2ErrorCode::ExpiredToken,
+            "invalid_token" => OAuth2ErrorCode::InvalidToken,
+            "insufficient_scope" => OAuth2ErrorCode::InsufficientScope,
+            "invalid_dpop_proof" => OAuth2ErrorCode::InvalidDPoPProof,
+            "use_dpop_nonce" => OAuth2ErrorCode::UseDPoPNonce,
+            "invalid_target" => OAuth2ErrorCode::InvalidTarget,
             s => OAuth2ErrorCode::Unrecognized(s.to_owned()),
         }
     }
 }
 
+/// Operations against an authorization server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// Authorization request.
+    ///
+    /// See [RFC 6749, section 4.1.1](http://tools.ietf.org/html/rfc6749#section-4.1.1).
+    Authorize,
+
+    /// Access token request.
+    ///
+    /// See [RFC 6749, section 4.1.3](http://tools.ietf.org/html/rfc6749#section-4.1.3).
+    Token,
+
+    /// Access token refresh.
+    ///
+    /// See [RFC 6749, section 6](http://tools.ietf.org/html/rfc6749#section-6).
+    Refresh,
+
+    /// Token revocation.
+    ///
+    /// See [RFC 7009](https://tools.ietf.org/html/rfc7009).
+    Revoke,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(match *self {
+            Operation::Authorize => "authorize",
+            Operation::Token => "token",
+            Operation::Refresh => "refresh",
+            Operation::Revoke => "revoke",
+        })
+    }
+}
+
 /// OAuth 2.0 error.
 ///
 /// See [RFC 6749, section 5.2](http://tools.ietf.org/html/rfc6749#section-5.2).
@@ -67,11 +249,17 @@ pub struct OAuth2Error {
 
     /// A URI identifying a human-readable web page with information about the error.
     pub uri: Option<String>,
+
+    /// The operation which failed, if known.
+    pub operation: Option<Operation>,
+
+    /// The endpoint of the operation which failed, if known.
+    pub endpoint: Option<Url>,
 }
 
 impl fmt::Display for OAuth2Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{:?}", self.code)?;
+        write!(f, "{}", self.code)?;
         if let Some(ref description) = self.description {
             write!(f, ": {}", description)?;
         }
@@ -82,17 +270,28 @@ impl fmt::Display for OAuth2Error {
     }
 }
 
-impl Error for OAuth2Error {
-    fn description(&self) -> &str { "OAuth 2.0 API error" }
+impl OAuth2Error {
+    /// Returns true if the same request may succeed if repeated.
+    ///
+    /// See `OAuth2ErrorCode::is_retryable`.
+    pub fn is_retryable(&self) -> bool { self.code.is_retryable() }
+
+    /// Returns true if the access token should be refreshed before retrying.
+    pub fn requires_refresh(&self) -> bool { self.code.requires_refresh() }
+
+    /// Returns true if the user must authorize the client again.
+    ///
+    /// See `OAuth2ErrorCode::requires_reauthorization`.
+    pub fn requires_reauthorization(&self) -> bool { self.code.requires_reauthorization() }
 }
 
+impl Error for OAuth2Error {}
+
 impl FromResponse for OAuth2Error {
     fn from_response(json: &Value) -> Result<Self, ParseError> {
-        let obj = json.as_object().ok_or(ParseError::ExpectedType("object"))?;
+        let obj = expect_object(json)?;
 
-        let code = obj.get("error")
-            .and_then(Value::as_str)
-            .ok_or(ParseError::ExpectedFieldType("error", "string"))?;
+        let code = str_field(obj, "error")?;
         let description = obj.get("error_description").and_then(Value::as_str);
         let uri = obj.get("error_uri").and_then(Value::as_str);
 
@@ -100,20 +299,228 @@ impl FromResponse for OAuth2Error {
             code: code.into(),
             description: description.map(Into::into),
             uri: uri.map(Into::into),
+            operation: None,
+            endpoint: None,
         })
     }
 }
 
+/// Bearer authentication challenge from a resource server's `WWW-Authenticate` header.
+///
+/// DPoP challenges share the same parameters and are represented by this type as well.
+///
+/// See [RFC 6750, section 3](http://tools.ietf.org/html/rfc6750#section-3) and [RFC 9449,
+/// section 7.1](https://www.rfc-editor.org/rfc/rfc9449#section-7.1).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BearerChallenge {
+    /// The scope of protection.
+    pub realm: Option<String>,
+
+    /// The scope necessary to access the requested resource.
+    pub scope: Option<String>,
+
+    /// Error code, if the request included an access token or was malformed.
+    ///
+    /// Resource servers use the codes of RFC 6750 and RFC 9449, which share the OAuth 2.0 error
+    /// registry, so `OAuth2ErrorCode::requires_refresh` and the like apply to them as well.
+    pub code: Option<OAuth2ErrorCode>,
+
+    /// Human-readable text providing additional information about the error.
+    pub description: Option<String>,
+
+    /// A URI identifying a human-readable web page with information about the error.
+    pub uri: Option<String>,
+}
+
+impl BearerChallenge {
+    /// Parses the Bearer challenge from a `WWW-Authenticate` header value.
+    ///
+    /// Returns `None` if the header does not contain a Bearer challenge.
+    pub fn parse(header: &str) -> Option<Self> {
+        BearerChallenge::parse_scheme(header, "Bearer")
+    }
+
+    /// Parses the challenge for an authentication scheme, such as `DPoP`, from a
+    /// `WWW-Authenticate` header value.
+    pub fn parse_scheme(header: &str, scheme: &str) -> Option<Self> {
+        parse_challenges(header).into_iter()
+            .find(|(s, _)| s.eq_ignore_ascii_case(scheme))
+            .map(|(_, params)| {
+                let param = |name: &str| {
+                    params.iter()
+                        .find(|&(k, _)| k.eq_ignore_ascii_case(name))
+                        .map(|(_, v)| v.clone())
+                };
+                BearerChallenge {
+                    realm: param("realm"),
+                    scope: param("scope"),
+                    code: param("error").map(|code| OAuth2ErrorCode::from(&code[..])),
+                    description: param("error_description"),
+                    uri: param("error_uri"),
+                }
+            })
+    }
+
+    /// Finds the Bearer challenge in a response's `WWW-Authenticate` headers.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        BearerChallenge::from_headers_scheme(headers, "Bearer")
+    }
+
+    /// Finds the challenge for an authentication scheme in a response's `WWW-Authenticate`
+    /// headers.
+    pub fn from_headers_scheme(headers: &HeaderMap, scheme: &str) -> Option<Self> {
+        headers.get_all(WWW_AUTHENTICATE).iter()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| BearerChallenge::parse_scheme(value, scheme))
+            .next()
+    }
+
+    /// Returns true if the access token is expired, revoked or otherwise invalid.
+    ///
+    /// Refreshing the token may resolve the error.
+    pub fn is_invalid_token(&self) -> bool {
+        self.code == Some(OAuth2ErrorCode::InvalidToken)
+    }
+
+    /// Returns true if the access token lacks the scope required by the resource.
+    ///
+    /// The user must authorize the additional scope, which is available in `scope`.
+    pub fn is_insufficient_scope(&self) -> bool {
+        self.code == Some(OAuth2ErrorCode::InsufficientScope)
+    }
+}
+
+impl fmt::Display for BearerChallenge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self.code {
+            Some(ref code) => write!(f, "{}", code)?,
+            None => write!(f, "Bearer authentication required")?,
+        }
+        if let Some(ref description) = self.description {
+            write!(f, ": {}", description)?;
+        }
+        if let Some(ref scope) = self.scope {
+            write!(f, " (scope {})", scope)?;
+        }
+        if let Some(ref uri) = self.uri {
+            write!(f, " ({})", uri)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for BearerChallenge {}
+
+/// Parses challenges into schemes and parameters.
+///
+/// See [RFC 7235, section 4.1](http://tools.ietf.org/html/rfc7235#section-4.1).
+fn parse_challenges(header: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut parser = ChallengeParser { chars: header.chars().collect(), pos: 0 };
+    let mut challenges = Vec::new();
+
+    loop {
+        parser.skip_separators();
+        if parser.at_end() { break; }
+
+        let scheme = parser.token();
+        if scheme.is_empty() {
+            // Skip anything which cannot start a challenge, such as stray token68 characters.
+            parser.pos += 1;
+            continue;
+        }
+
+        let mut params = Vec::new();
+        loop {
+            let start = parser.pos;
+            parser.skip_separators();
+            let name = parser.token();
+            if name.is_empty() {
+                // A token68 value, which Bearer challenges never use.
+                parser.skip_while(|c| c != ',');
+                break;
+            }
+            parser.skip_whitespace();
+            if parser.peek() != Some('=') {
+                // The start of the next challenge.
+                parser.pos = start;
+                break;
+            }
+            parser.pos += 1;
+            parser.skip_whitespace();
+            let value = if parser.peek() == Some('"') {
+                parser.quoted_string()
+            } else {
+                parser.token()
+            };
+            params.push((name, value));
+        }
+
+        challenges.push((scheme, params));
+    }
+
+    challenges
+}
+
+struct ChallengeParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl ChallengeParser {
+    fn at_end(&self) -> bool { self.pos >= self.chars.len() }
+
+    fn peek(&self) -> Option<char> { self.chars.get(self.pos).cloned() }
+
+    fn skip_while<F: Fn(char) -> bool>(&mut self, f: F) {
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.skip_while(|c| c == ' ' || c == '\t');
+    }
+
+    fn skip_separators(&mut self) {
+        self.skip_while(|c| c == ' ' || c == '\t' || c == ',');
+    }
+
+    fn token(&mut self) -> String {
+        let start = self.pos;
+        self.skip_while(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn quoted_string(&mut self) -> String {
+        let mut value = String::new();
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '"' => break,
+                '\\' => {
+                    if let Some(c) = self.peek() {
+                        value.push(c);
+                        self.pos += 1;
+                    }
+                },
+                c => value.push(c),
+            }
+        }
+        value
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use client::response::{FromResponse, ParseError};
-    use super::{OAuth2Error, OAuth2ErrorCode};
+    use super::{BearerChallenge, OAuth2Error, OAuth2ErrorCode};
 
     #[test]
     fn from_response_empty() {
         let json = "{}".parse().unwrap();
         assert_eq!(
-            ParseError::ExpectedFieldType("error", "string"),
+            ParseError::expected_field_type("error", "string"),
             OAuth2Error::from_response(&json).unwrap_err()
         );
     }
@@ -126,6 +533,8 @@ mod tests {
                 code: OAuth2ErrorCode::InvalidRequest,
                 description: None,
                 uri: None,
+                operation: None,
+                endpoint: None,
             },
             OAuth2Error::from_response(&json).unwrap()
         );
@@ -141,6 +550,8 @@ mod tests {
                 code: OAuth2ErrorCode::InvalidRequest,
                 description: Some(String::from("foo")),
                 uri: None,
+                operation: None,
+                endpoint: None,
             },
             OAuth2Error::from_response(&json).unwrap()
         );
@@ -156,8 +567,118 @@ mod tests {
                 code: OAuth2ErrorCode::InvalidRequest,
                 description: None,
                 uri: Some(String::from("http://example.com")),
+                operation: None,
+                endpoint: None,
             },
             OAuth2Error::from_response(&json).unwrap()
         );
     }
+
+    #[test]
+    fn error_codes() {
+        for code in &[
+            "invalid_request", "invalid_client", "invalid_grant", "unauthorized_client",
+            "unsupported_grant_type", "invalid_scope", "server_error", "temporarily_unavailable",
+            "access_denied", "authorization_pending", "slow_down", "expired_token",
+            "invalid_token", "insufficient_scope", "invalid_dpop_proof", "use_dpop_nonce",
+            "invalid_target",
+        ] {
+            let parsed = OAuth2ErrorCode::from(*code);
+            assert_ne!(OAuth2ErrorCode::Unrecognized(code.to_string()), parsed);
+            assert_eq!(*code, parsed.as_str());
+        }
+        assert_eq!(
+            OAuth2ErrorCode::Unrecognized(String::from("foo")),
+            OAuth2ErrorCode::from("foo")
+        );
+    }
+
+    #[test]
+    fn error_classification() {
+        assert!(OAuth2ErrorCode::SlowDown.is_retryable());
+        assert!(OAuth2ErrorCode::TemporarilyUnavailable.is_retryable());
+        assert!(!OAuth2ErrorCode::InvalidGrant.is_retryable());
+        assert!(!OAuth2ErrorCode::ServerError.is_retryable());
+
+        assert!(OAuth2ErrorCode::InvalidToken.requires_refresh());
+        assert!(!OAuth2ErrorCode::InvalidToken.requires_reauthorization());
+
+        assert!(OAuth2ErrorCode::InvalidGrant.requires_reauthorization());
+        assert!(OAuth2ErrorCode::ExpiredToken.requires_reauthorization());
+        assert!(!OAuth2ErrorCode::InvalidClient.requires_reauthorization());
+        assert!(!OAuth2ErrorCode::Unrecognized(String::from("foo")).is_retryable());
+    }
+
+    #[test]
+    fn bearer_challenge_realm() {
+        assert_eq!(
+            Some(BearerChallenge {
+                realm: Some(String::from("example")),
+                scope: None,
+                code: None,
+                description: None,
+                uri: None,
+            }),
+            BearerChallenge::parse(r#"Bearer realm="example""#)
+        );
+    }
+
+    #[test]
+    fn bearer_challenge_invalid_token() {
+        let challenge = BearerChallenge::parse(
+            r#"Bearer realm="example", error="invalid_token", error_description="The access token expired""#
+        ).unwrap();
+        assert_eq!(Some(String::from("example")), challenge.realm);
+        assert_eq!(Some(OAuth2ErrorCode::InvalidToken), challenge.code);
+        assert_eq!(Some(String::from("The access token expired")), challenge.description);
+        assert!(challenge.is_invalid_token());
+        assert!(!challenge.is_insufficient_scope());
+        assert_eq!("invalid_token: The access token expired", challenge.to_string());
+        assert!(challenge.code.as_ref().is_some_and(OAuth2ErrorCode::requires_refresh));
+    }
+
+    #[test]
+    fn bearer_challenge_insufficient_scope() {
+        let challenge = BearerChallenge::parse(
+            r#"Bearer error=insufficient_scope, scope="read write", error_uri="http://example.com/scope""#
+        ).unwrap();
+        assert_eq!(Some(OAuth2ErrorCode::InsufficientScope), challenge.code);
+        assert_eq!(Some(String::from("read write")), challenge.scope);
+        assert_eq!(Some(String::from("http://example.com/scope")), challenge.uri);
+        assert!(challenge.is_insufficient_scope());
+        assert_eq!(
+            "insufficient_scope (scope read write) (http://example.com/scope)",
+            challenge.to_string()
+        );
+    }
+
+    #[test]
+    fn bearer_challenge_among_others() {
+        let challenge = BearerChallenge::parse(
+            r#"Basic realm="basic", Newauth realm="apps", type=1, title="Login to \"apps\"", bearer error="invalid_request""#
+        ).unwrap();
+        assert_eq!(None, challenge.realm);
+        assert_eq!(Some(OAuth2ErrorCode::InvalidRequest), challenge.code);
+    }
+
+    #[test]
+    fn bearer_challenge_after_token68() {
+        let challenge = BearerChallenge::parse(r#"Negotiate a87421000492aa874209af8bc028==, Bearer error="invalid_token""#)
+            .unwrap();
+        assert!(challenge.is_invalid_token());
+    }
+
+    #[test]
+    fn dpop_challenge() {
+        let header = r#"Bearer realm="bearer", DPoP error="use_dpop_nonce", algs="ES256 PS256""#;
+        let challenge = BearerChallenge::parse_scheme(header, "DPoP").unwrap();
+        assert_eq!(Some(OAuth2ErrorCode::UseDPoPNonce), challenge.code);
+        assert_eq!(Some(String::from("bearer")), BearerChallenge::parse(header).unwrap().realm);
+    }
+
+    #[test]
+    fn bearer_challenge_missing() {
+        assert_eq!(None, BearerChallenge::parse(r#"Basic realm="example""#));
+        assert_eq!(None, BearerChallenge::parse(""));
+    }
 }
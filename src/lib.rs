@@ -23,8 +23,8 @@
 //!
 //! ## Token types
 //!
-//! The only supported token type is Bearer. Support for others can be added by implementing the
-//! `Token` trait.
+//! The supported token types are Bearer and DPoP. Support for others can be added by implementing
+//! the `Token` trait.
 //!
 //! ## Examples
 //!
@@ -103,6 +103,33 @@
 //! # }
 //! ```
 //!
+//! ### Sharing access tokens between threads
+//!
+//! ```no_run
+//! # extern crate inth_oauth2;
+//! # extern crate reqwest;
+//! # use inth_oauth2::Client;
+//! # use inth_oauth2::provider::google::Installed;
+//! use std::sync::Arc;
+//! use std::thread;
+//! use inth_oauth2::TokenManager;
+//!
+//! # fn main() {
+//! # let client = Client::new(Installed, String::new(), String::new(), None);
+//! # let http = reqwest::Client::new();
+//! # let token = client.request_token(&http, "").unwrap();
+//! let manager = Arc::new(TokenManager::new(client, http, token));
+//!
+//! let worker = {
+//!     let manager = manager.clone();
+//!     thread::spawn(move || {
+//!         // Refreshes the token if it is about to expire.
+//!         let access_token = manager.access_token().unwrap();
+//!     })
+//! };
+//! # }
+//! ```
+//!
 //! ### Using bearer access tokens
 //!
 //! ```no_run
@@ -122,9 +149,53 @@
 //! # }
 //! ```
 //!
+//! Bearer tokens can also send themselves as a form-encoded body or URI query parameter, for
+//! resource servers which do not accept the `Authorization` header.
+//!
+//! ```no_run
+//! # extern crate inth_oauth2;
+//! # extern crate reqwest;
+//! # use inth_oauth2::Client;
+//! # use inth_oauth2::provider::Imgur;
+//! use inth_oauth2::token::Transmission;
+//!
+//! # fn main() {
+//! # let oauth_client = Client::new(Imgur, String::new(), String::new(), None);
+//! # let http = reqwest::Client::new();
+//! # let token = oauth_client.request_token(&http, "").unwrap();
+//! let request = token.authorize_with(
+//!     http.get("https://example.com/resource"),
+//!     Transmission::Query,
+//! );
+//! # }
+//! ```
+//!
+//! An `AuthorizedClient` attaches a valid access token to every request, refreshing the token
+//! and replaying the request once if the resource server rejects it.
+//!
+//! ```no_run
+//! # extern crate inth_oauth2;
+//! # extern crate reqwest;
+//! # use inth_oauth2::Client;
+//! # use inth_oauth2::provider::google::Installed;
+//! use inth_oauth2::AuthorizedClient;
+//!
+//! # fn main() {
+//! # let oauth_client = Client::new(Installed, String::new(), String::new(), None);
+//! # let http = reqwest::Client::new();
+//! # let token = oauth_client.request_token(&http, "").unwrap();
+//! let authorized = AuthorizedClient::new(oauth_client, http, token);
+//! let request = authorized.get("https://example.com/resource");
+//! let response = authorized.send(request).unwrap();
+//! # }
+//! ```
+//!
 //! ### Persisting tokens
 //!
-//! All token types implement `Serialize` and `Deserialize` from `serde`.
+//! All token types implement `Serialize` and `Deserialize` from `serde`, using the versioned
+//! `StoredToken` format. Tokens saved by earlier versions are migrated when deserialized, and
+//! tokens can be deserialized with a richer lifetime type than they were saved with, such as a
+//! `Bearer<Expiring>` as a `Bearer<Flexible>`.
 //!
 //! ```no_run
 //! # extern crate inth_oauth2;
@@ -139,6 +210,34 @@
 //! let json = serde_json::to_string(&token).unwrap();
 //! # }
 //! ```
+//!
+//! Tokens can also be saved to a `TokenStore`. A `TokenManager` with a store saves every
+//! refreshed token automatically.
+//!
+//! ```no_run
+//! # extern crate inth_oauth2;
+//! # extern crate reqwest;
+//! # use inth_oauth2::{Client, TokenManager};
+//! # use inth_oauth2::provider::google::Installed;
+//! use inth_oauth2::store::{FileStore, TokenStore};
+//!
+//! # fn main() {
+//! # let http = reqwest::Client::new();
+//! # let client = Client::new(Installed, String::new(), String::new(), None);
+//! let store = FileStore::new("/home/user/.config/example/tokens");
+//! let token = match store.load("user@example.com").unwrap() {
+//!     Some(token) => token,
+//!     None => {
+//!         let token = client.request_token(&http, "").unwrap();
+//!         store.save("user@example.com", &token).unwrap();
+//!         token
+//!     }
+//! };
+//!
+//! let manager = TokenManager::new(client, http, token)
+//!     .with_store(store, "user@example.com");
+//! # }
+//! ```
 
 #![warn(
     missing_docs,
@@ -158,15 +257,42 @@ extern crate lazy_static;
 #[macro_use]
 extern crate serde_derive;
 
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate serde_json;
+
+extern crate argon2;
+extern crate base64;
+extern crate chacha20poly1305;
 extern crate chrono;
+extern crate fs2;
+extern crate hyper;
+extern crate p256;
+extern crate rand_core;
 extern crate reqwest;
-extern crate serde_json;
+extern crate serde;
+extern crate sha2;
 extern crate url;
+extern crate zeroize;
 
 pub mod token;
 pub mod provider;
 pub mod error;
 pub mod client;
+pub mod authorized;
+pub mod coordinator;
+pub mod dpop;
+pub mod manager;
+pub mod secret;
+pub mod store;
+
+#[cfg(test)]
+mod test_support;
 
 pub use token::{Token, Lifetime};
-pub use client::{Client, ClientError};
+pub use secret::Secret;
+pub use client::{Client, ClientError, ClientErrorKind};
+pub use manager::TokenManager;
+pub use authorized::AuthorizedClient;
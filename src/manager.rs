@@ -0,0 +1,332 @@
+//! Token management.
+
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use chrono::{Duration, Utc};
+use reqwest;
+
+use client::{Client, ClientError, Refreshed};
+use provider::Provider;
+use secret::Secret;
+use store::TokenStore;
+use token::{Lifetime, Refreshable, Token};
+
+/// Thread-safe access token manager.
+///
+/// Holds a client, an HTTP client and the current token, and hands out valid access tokens on
+/// demand. Tokens are refreshed shortly before they expire; tokens without a known expiry time
+/// are only refreshed by `force_refresh`. When several threads find the token in need of
+/// refreshing at once, only one refresh request is sent and the others wait for its result.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate inth_oauth2;
+/// # extern crate reqwest;
+/// use inth_oauth2::TokenManager;
+/// # use inth_oauth2::Client;
+/// # use inth_oauth2::provider::google::Installed;
+/// # fn main() {
+/// # let client = Client::new(Installed, String::new(), String::new(), None);
+/// # let http = reqwest::Client::new();
+/// # let token = client.request_token(&http, "").unwrap();
+///
+/// let manager = TokenManager::new(client, http, token);
+/// let access_token = manager.access_token().unwrap();
+/// # }
+/// ```
+pub struct TokenManager<P: Provider> {
+    client: Client<P>,
+    http_client: reqwest::Client,
+    token: RwLock<P::Token>,
+    refresh_lock: Mutex<()>,
+    margin: Duration,
+    refresh_fraction: Option<f64>,
+    store: Option<(BoxedStore<P::Token>, String)>,
+    callbacks: Vec<Callback<P::Token>>,
+}
+
+type BoxedStore<T> = Box<dyn TokenStore<T> + Send + Sync>;
+type Callback<T> = Box<dyn Fn(&Refreshed<T>) + Send + Sync>;
+
+impl<P> fmt::Debug for TokenManager<P>
+where P: Provider + fmt::Debug, P::Token: fmt::Debug {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("TokenManager")
+            .field("client", &self.client)
+            .field("http_client", &self.http_client)
+            .field("token", &self.token)
+            .field("margin", &self.margin)
+            .field("refresh_fraction", &self.refresh_fraction)
+            .field("account", &self.store.as_ref().map(|(_, account)| account))
+            .field("callbacks", &self.callbacks.len())
+            .finish()
+    }
+}
+
+impl<P> TokenManager<P> where P: Provider, P::Lifetime: Refreshable, P::Token: Clone {
+    /// Creates a token manager.
+    ///
+    /// Tokens are refreshed when they are within 60 seconds of expiring.
+    pub fn new(client: Client<P>, http_client: reqwest::Client, token: P::Token) -> Self {
+        TokenManager {
+            client,
+            http_client,
+            token: RwLock::new(token),
+            refresh_lock: Mutex::new(()),
+            margin: Duration::seconds(60),
+            refresh_fraction: None,
+            store: None,
+            callbacks: Vec::new(),
+        }
+    }
+
+    /// Saves every refreshed token to a store under an account name.
+    pub fn with_store<S>(mut self, store: S, account: &str) -> Self
+    where S: TokenStore<P::Token> + Send + Sync + 'static {
+        self.store = Some((Box::new(store), account.to_owned()));
+        self
+    }
+
+    /// Adds a callback invoked with every newly issued token.
+    ///
+    /// Callbacks run while other threads wait for the refresh to complete, after the token has
+    /// been saved to the store, if any. They run even if saving the token failed.
+    pub fn on_refresh<F>(mut self, callback: F) -> Self
+    where F: Fn(&Refreshed<P::Token>) + Send + Sync + 'static {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Sets how long before expiry tokens are refreshed.
+    pub fn with_margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Also refreshes tokens once a fraction of their lifetime has elapsed, such as `0.8`.
+    ///
+    /// Only applies to tokens whose issue time is known.
+    pub fn with_refresh_fraction(mut self, fraction: f64) -> Self {
+        self.refresh_fraction = Some(fraction);
+        self
+    }
+
+    /// Returns the client.
+    pub fn client(&self) -> &Client<P> { &self.client }
+
+    /// Returns the HTTP client.
+    pub fn http_client(&self) -> &reqwest::Client { &self.http_client }
+
+    /// Returns a valid token, refreshing it if necessary.
+    pub fn token(&self) -> Result<P::Token, ClientError> {
+        {
+            let token = self.read();
+            if !self.needs_refresh(&token) {
+                return Ok(token.clone());
+            }
+        }
+
+        let _guard = self.lock_refresh();
+
+        // Another thread may have refreshed the token while we waited.
+        let token = self.read().clone();
+        if !self.needs_refresh(&token) {
+            return Ok(token);
+        }
+
+        self.refresh_locked(&token)
+    }
+
+    /// Refreshes the token after its access token was rejected, regardless of its expiry.
+    ///
+    /// If the token has already been replaced since the rejected access token was handed out,
+    /// the current token is returned without refreshing it again.
+    pub fn force_refresh(&self, rejected: &str) -> Result<P::Token, ClientError> {
+        let _guard = self.lock_refresh();
+
+        let token = self.read().clone();
+        if token.access_token() != rejected {
+            return Ok(token);
+        }
+
+        self.refresh_locked(&token)
+    }
+
+    /// Returns a valid access token, refreshing the token if necessary.
+    pub fn access_token(&self) -> Result<Secret, ClientError> {
+        self.token().map(|token| Secret::from(token.access_token()))
+    }
+
+    fn refresh_locked(&self, token: &P::Token) -> Result<P::Token, ClientError> {
+        let refreshed = self.client.refresh(&self.http_client, token, None)?;
+        *self.write() = refreshed.token.clone();
+        let saved = match self.store {
+            Some((ref store, ref account)) => store.save(account, &refreshed.token),
+            None => Ok(()),
+        };
+        for callback in &self.callbacks {
+            callback(&refreshed);
+        }
+        saved.map(|()| refreshed.token)
+    }
+
+    fn needs_refresh(&self, token: &P::Token) -> bool {
+        let lifetime = token.lifetime();
+        lifetime.expires_at().is_some_and(|expires| expires - self.margin <= Utc::now())
+            || self.refresh_fraction.is_some_and(|fraction| {
+                lifetime.elapsed_fraction().is_some_and(|elapsed| elapsed >= fraction)
+            })
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, P::Token> {
+        self.token.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, P::Token> {
+        self.token.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lock_refresh(&self) -> MutexGuard<'_, ()> {
+        self.refresh_lock.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time;
+
+    use chrono::Duration;
+    use reqwest;
+
+    use client::Client;
+    use store::{MemoryStore, TokenStore};
+    use test_support::{token_with_refresh, ReadOnlyStore, Request, Response, Server, TestProvider};
+    use token::{Bearer, Refresh, Token};
+    use super::TokenManager;
+
+    type Test = TestProvider<Bearer<Refresh>, Refresh>;
+
+    fn manager(server: &Server, expires_in: i64) -> TokenManager<Test> {
+        let client = Client::new(Test::on(server), String::from("foo"), String::from("bar"), None);
+        let token = token_with_refresh("Bearer", "aaaaaaaa", "bbbbbbbb", expires_in);
+        TokenManager::new(client, reqwest::Client::new(), token)
+    }
+
+    fn refreshed(_: usize, _: &Request) -> Response {
+        thread::sleep(time::Duration::from_millis(100));
+        Response::json(200, r#"{"token_type":"Bearer","access_token":"cccccccc","expires_in":3600}"#)
+    }
+
+    #[test]
+    fn token_valid() {
+        let server = Server::new(refreshed);
+        let manager = manager(&server, 3600);
+        assert_eq!("aaaaaaaa", manager.access_token().unwrap().expose());
+        assert_eq!(0, server.hits());
+    }
+
+    #[test]
+    fn token_near_expiry() {
+        let server = Server::new(refreshed);
+        let manager = manager(&server, 30);
+        let token = manager.token().unwrap();
+        assert_eq!("cccccccc", token.access_token());
+        assert_eq!("bbbbbbbb", token.lifetime().refresh_token());
+        assert_eq!("cccccccc", manager.access_token().unwrap().expose());
+        assert_eq!(1, server.hits());
+    }
+
+    #[test]
+    fn token_refresh_fraction() {
+        let server = Server::new(refreshed);
+        let manager = manager(&server, 4)
+            .with_margin(Duration::zero())
+            .with_refresh_fraction(0.25);
+        assert_eq!("aaaaaaaa", manager.access_token().unwrap().expose());
+        thread::sleep(time::Duration::from_millis(1100));
+        assert_eq!("cccccccc", manager.access_token().unwrap().expose());
+        assert_eq!(1, server.hits());
+    }
+
+    #[test]
+    fn token_saved_to_store() {
+        let server = Server::new(refreshed);
+        let store = Arc::new(MemoryStore::new());
+        let manager = manager(&server, 0).with_store(store.clone(), "foo");
+        manager.token().unwrap();
+        let saved: Bearer<Refresh> = store.load("foo").unwrap().unwrap();
+        assert_eq!("cccccccc", saved.access_token());
+    }
+
+    #[test]
+    fn token_refresh_callback() {
+        let server = Server::new(|n, _: &Request| {
+            if n == 0 {
+                Response::json(200, r#"{"token_type":"Bearer","access_token":"cccccccc","expires_in":0}"#)
+            } else {
+                Response::json(200, r#"{"token_type":"Bearer","access_token":"dddddddd","expires_in":0,"refresh_token":"eeeeeeee"}"#)
+            }
+        });
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let manager = {
+            let calls = calls.clone();
+            manager(&server, 0).on_refresh(move |refreshed| {
+                let token = refreshed.token.access_token().to_owned();
+                calls.lock().unwrap().push((token, refreshed.rotated));
+            })
+        };
+        manager.token().unwrap();
+        manager.token().unwrap();
+        assert_eq!(
+            vec![(String::from("cccccccc"), false), (String::from("dddddddd"), true)],
+            *calls.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn token_refresh_callback_unsaved() {
+        let server = Server::new(refreshed);
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let manager = {
+            let calls = calls.clone();
+            manager(&server, 0)
+                .with_store(ReadOnlyStore(MemoryStore::new()), "foo")
+                .on_refresh(move |refreshed| {
+                    calls.lock().unwrap().push(refreshed.token.access_token().to_owned());
+                })
+        };
+        assert!(manager.token().is_err());
+        assert_eq!(vec![String::from("cccccccc")], *calls.lock().unwrap());
+        assert_eq!("cccccccc", manager.access_token().unwrap().expose());
+        assert_eq!(1, server.hits());
+    }
+
+    #[test]
+    fn force_refresh() {
+        let server = Server::new(refreshed);
+        let manager = manager(&server, 3600);
+        assert_eq!("cccccccc", manager.force_refresh("aaaaaaaa").unwrap().access_token());
+        assert_eq!("cccccccc", manager.force_refresh("aaaaaaaa").unwrap().access_token());
+        assert_eq!(1, server.hits());
+    }
+
+    #[test]
+    fn token_single_flight() {
+        let server = Server::new(refreshed);
+        let manager = Arc::new(manager(&server, 0));
+
+        let threads: Vec<_> = (0..8).map(|_| {
+            let manager = manager.clone();
+            thread::spawn(move || manager.access_token().unwrap())
+        }).collect();
+
+        for thread in threads {
+            assert_eq!("cccccccc", thread.join().unwrap().expose());
+        }
+        assert_eq!(1, server.hits());
+    }
+}
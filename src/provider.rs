@@ -2,6 +2,7 @@
 
 use url::Url;
 
+use client::response::ParsePolicy;
 use token::{Token, Lifetime, Bearer, Static, Refresh};
 
 /// OAuth 2.0 providers.
@@ -29,6 +30,11 @@ pub trait Provider {
     ///
     /// See [RFC 6749, section 2.3.1](http://tools.ietf.org/html/rfc6749#section-2.3.1).
     fn credentials_in_body(&self) -> bool { false }
+
+    /// The policy for parsing token responses.
+    ///
+    /// Providers whose responses do not conform to RFC 6749 can return `ParsePolicy::lenient()`.
+    fn parse_policy(&self) -> ParsePolicy { ParsePolicy::default() }
 }
 
 /// Google OAuth 2.0 providers.
@@ -46,14 +52,14 @@ pub mod google {
     /// See [Choosing a redirect URI][uri].
     ///
     /// [uri]: https://developers.google.com/identity/protocols/OAuth2InstalledApp#choosingredirecturi
-    pub const REDIRECT_URI_OOB: &'static str = "urn:ietf:wg:oauth:2.0:oob";
+    pub const REDIRECT_URI_OOB: &str = "urn:ietf:wg:oauth:2.0:oob";
 
     /// Signals the server to return the authorization code in the page title.
     ///
     /// See [Choosing a redirect URI][uri].
     ///
     /// [uri]: https://developers.google.com/identity/protocols/OAuth2InstalledApp#choosingredirecturi
-    pub const REDIRECT_URI_OOB_AUTO: &'static str = "urn:ietf:wg:oauth:2.0:oob:auto";
+    pub const REDIRECT_URI_OOB_AUTO: &str = "urn:ietf:wg:oauth:2.0:oob:auto";
 
     lazy_static! {
         static ref AUTH_URI: Url = Url::parse("https://accounts.google.com/o/oauth2/v2/auth").unwrap();
@@ -0,0 +1,186 @@
+//! Secret values.
+
+use std::fmt;
+use std::ops::Deref;
+
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// A secret string, such as a client secret, access token or refresh token.
+///
+/// `Debug` and `Display` show a fingerprint of the value instead of the value itself, so secrets
+/// can be logged along with the structures holding them without leaking. The value is only
+/// revealed by `expose`.
+///
+/// The value is zeroed in memory when the secret is dropped, including when it is overwritten by
+/// assignment, such as when a token is replaced by a refreshed one.
+///
+/// Secrets serialize as plain strings.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Wraps a secret value.
+    pub fn new(value: String) -> Self {
+        Secret(value)
+    }
+
+    /// Reveals the secret value.
+    pub fn expose(&self) -> &str { &self.0 }
+
+    /// Returns a fingerprint of the value: the first 8 hex digits of its SHA-256 hash.
+    ///
+    /// Fingerprints tell secrets apart in logs without revealing them.
+    pub fn fingerprint(&self) -> String {
+        Sha256::digest(self.0.as_bytes())[..4]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+impl Zeroize for Secret {
+    fn zeroize(&mut self) { self.0.zeroize() }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) { self.zeroize() }
+}
+
+impl ZeroizeOnDrop for Secret {}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self { Secret(value) }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self { Secret(value.to_owned()) }
+}
+
+/// Compares in constant time with respect to the contents, to avoid leaking them through timing.
+impl PartialEq for Secret {
+    fn eq(&self, other: &Secret) -> bool {
+        let (a, b) = (self.0.as_bytes(), other.0.as_bytes());
+        a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+    }
+}
+
+impl Eq for Secret {}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Secret(sha256:{})", self.fingerprint())
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "[redacted sha256:{}]", self.fingerprint())
+    }
+}
+
+/// A JSON value whose strings are zeroed in memory when it is dropped, such as a token response.
+pub(crate) struct SecretJson(pub Value);
+
+impl Deref for SecretJson {
+    type Target = Value;
+    fn deref(&self) -> &Value { &self.0 }
+}
+
+impl Drop for SecretJson {
+    fn drop(&mut self) { zeroize_json(&mut self.0) }
+}
+
+fn zeroize_json(value: &mut Value) {
+    match *value {
+        Value::String(ref mut s) => s.zeroize(),
+        Value::Array(ref mut values) => values.iter_mut().for_each(zeroize_json),
+        Value::Object(ref mut map) => map.values_mut().for_each(zeroize_json),
+        _ => {},
+    }
+}
+
+/// Returns true if a field might hold a secret, judging by its name.
+pub(crate) fn may_be_secret(field: &str) -> bool {
+    let field = field.to_ascii_lowercase();
+    if field == "token_type" || field.ends_with("_in") {
+        return false;
+    }
+    ["token", "secret", "password", "code", "assertion", "key"].iter()
+        .any(|word| field.contains(word))
+}
+
+/// Formats JSON fields for `Debug`, replacing values of fields which might hold secrets by a
+/// fingerprint.
+pub(crate) struct RedactedFields<'a>(pub &'a Map<String, Value>);
+
+impl<'a> fmt::Debug for RedactedFields<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let mut map = f.debug_map();
+        for (key, value) in self.0 {
+            if may_be_secret(key) {
+                let secret = value.as_str().map_or_else(|| value.to_string(), From::from);
+                map.entry(key, &Secret::new(secret));
+            } else {
+                map.entry(key, value);
+            }
+        }
+        map.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use zeroize::Zeroize;
+
+    use super::{zeroize_json, RedactedFields, Secret};
+
+    #[test]
+    fn redacted() {
+        let secret = Secret::from("hunter2");
+        assert_eq!("hunter2", secret.expose());
+        assert_eq!("f52fbd32", secret.fingerprint());
+        assert_eq!("Secret(sha256:f52fbd32)", format!("{:?}", secret));
+        assert_eq!("[redacted sha256:f52fbd32]", secret.to_string());
+    }
+
+    #[test]
+    fn eq() {
+        assert_eq!(Secret::from("hunter2"), Secret::from(String::from("hunter2")));
+        assert_ne!(Secret::from("hunter2"), Secret::from("hunter3"));
+        assert_ne!(Secret::from("hunter2"), Secret::from("hunter22"));
+    }
+
+    #[test]
+    fn zeroize() {
+        let mut secret = Secret::from("hunter2");
+        secret.zeroize();
+        assert_eq!("", secret.expose());
+    }
+
+    #[test]
+    fn zeroize_json_strings() {
+        let mut json = json!({"access_token": "aaaaaaaa", "scopes": ["foo"], "expires_in": 3600});
+        zeroize_json(&mut json);
+        assert_eq!(json!({"access_token": "", "scopes": [""], "expires_in": 3600}), json);
+    }
+
+    #[test]
+    fn serde() {
+        let secret = Secret::from("hunter2");
+        assert_eq!(r#""hunter2""#, serde_json::to_string(&secret).unwrap());
+        assert_eq!(secret, serde_json::from_str(r#""hunter2""#).unwrap());
+    }
+
+    #[test]
+    fn redacted_fields() {
+        let fields = json!({"id_token": "eyJhbGciOiJub25lIn0", "user_id": 42});
+        let debug = format!("{:?}", RedactedFields(fields.as_object().unwrap()));
+        assert!(!debug.contains("eyJhbGciOiJub25lIn0"), "{}", debug);
+        assert!(debug.contains("\"id_token\": Secret(sha256:"), "{}", debug);
+        assert!(debug.contains("\"user_id\": Number(42)"), "{}", debug);
+    }
+}
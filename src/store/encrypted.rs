@@ -0,0 +1,349 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use zeroize::{Zeroize, Zeroizing};
+
+use client::ClientError;
+use secret::Secret;
+use store::{FileStore, TokenStore};
+
+const MAGIC: &[u8; 4] = b"ITOK";
+const VERSION: u8 = 1;
+const KDF_NONE: u8 = 0;
+const KDF_ARGON2ID: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Maximum Argon2id costs accepted from a file, so a corrupted or tampered file cannot exhaust
+/// memory or time before its tag is checked.
+const MAX_M_COST: u32 = 4 * Params::DEFAULT_M_COST;
+const MAX_T_COST: u32 = 4 * Params::DEFAULT_T_COST;
+const MAX_P_COST: u32 = 4 * Params::DEFAULT_P_COST;
+
+/// A token store writing each account's token to an encrypted file in a directory.
+///
+/// Tokens are serialized as JSON and encrypted with XChaCha20-Poly1305, using either a 256-bit key
+/// or a key derived from a passphrase with Argon2id. The account name is authenticated along with
+/// the token, so files cannot be swapped between accounts. Files are written the same way as by
+/// `FileStore`, with the `enc` extension.
+///
+/// # File format
+///
+/// Version 1 files consist of:
+///
+/// - the magic bytes `ITOK`,
+/// - a version byte, `1`,
+/// - a key derivation byte, `0` for a key or `1` for Argon2id,
+/// - for Argon2id, the memory cost, time cost and parallelism as big-endian `u32`s followed by a
+///   16 byte salt,
+/// - a 24 byte nonce,
+/// - the ciphertext and tag.
+///
+/// Everything preceding the ciphertext is authenticated as associated data. Files asking for more
+/// than four times the default Argon2id costs are rejected.
+pub struct EncryptedFileStore {
+    files: FileStore,
+    key: KeySource,
+}
+
+enum KeySource {
+    Key(Key),
+    Passphrase(Secret, Params),
+}
+
+impl Drop for KeySource {
+    fn drop(&mut self) {
+        if let KeySource::Key(ref mut key) = *self {
+            key.as_mut_slice().zeroize();
+        }
+    }
+}
+
+impl fmt::Debug for EncryptedFileStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let key = match self.key {
+            KeySource::Key(_) => "Key(..)",
+            KeySource::Passphrase(..) => "Passphrase(..)",
+        };
+        f.debug_struct("EncryptedFileStore")
+            .field("dir", &self.files.dir())
+            .field("key", &key)
+            .finish()
+    }
+}
+
+impl EncryptedFileStore {
+    /// Creates a store in a directory, encrypting tokens with a 256-bit key.
+    pub fn new<D: Into<PathBuf>>(dir: D, key: [u8; 32]) -> Self {
+        EncryptedFileStore {
+            files: FileStore::with_extension(dir, "enc"),
+            key: KeySource::Key(key.into()),
+        }
+    }
+
+    /// Creates a store in a directory, encrypting tokens with keys derived from a passphrase.
+    ///
+    /// A fresh salt is used for every file, so saving and loading each derive a key.
+    pub fn with_passphrase<D: Into<PathBuf>>(dir: D, passphrase: Secret) -> Self {
+        EncryptedFileStore {
+            files: FileStore::with_extension(dir, "enc"),
+            key: KeySource::Passphrase(passphrase, Params::default()),
+        }
+    }
+
+    /// Returns the directory tokens are stored in.
+    pub fn dir(&self) -> &Path { self.files.dir() }
+
+    /// Returns the path of the file an account's token is stored in.
+    pub fn path(&self, account: &str) -> PathBuf { self.files.path(account) }
+
+    fn encrypt(&self, account: &str, plaintext: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+
+        let derived;
+        let key: &Key = match self.key {
+            KeySource::Key(ref key) => {
+                header.push(KDF_NONE);
+                key
+            },
+            KeySource::Passphrase(ref passphrase, ref params) => {
+                let mut salt = [0; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                header.push(KDF_ARGON2ID);
+                header.extend_from_slice(&params.m_cost().to_be_bytes());
+                header.extend_from_slice(&params.t_cost().to_be_bytes());
+                header.extend_from_slice(&params.p_cost().to_be_bytes());
+                header.extend_from_slice(&salt);
+                derived = derive_key(passphrase.expose(), &salt, params.clone())?;
+                &derived
+            },
+        };
+
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        header.extend_from_slice(&nonce);
+
+        let aad = associated_data(&header, account);
+        let ciphertext = XChaCha20Poly1305::new(key)
+            .encrypt(&nonce, Payload { msg: plaintext, aad: &aad })
+            .map_err(|_| invalid_data("token encryption failed"))?;
+
+        header.extend_from_slice(&ciphertext);
+        Ok(header)
+    }
+
+    fn decrypt(&self, account: &str, bytes: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut reader = Reader { bytes, pos: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid_data("not an encrypted token file"));
+        }
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(invalid_data(&format!("unsupported token file version {}", version)));
+        }
+
+        let derived;
+        let key: &Key = match (reader.take(1)?[0], &self.key) {
+            (KDF_NONE, KeySource::Key(key)) => key,
+            (KDF_ARGON2ID, KeySource::Passphrase(passphrase, _)) => {
+                let m_cost = reader.take_u32()?;
+                let t_cost = reader.take_u32()?;
+                let p_cost = reader.take_u32()?;
+                let salt = reader.take(SALT_LEN)?;
+                if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+                    return Err(invalid_data("token file key derivation costs too high"));
+                }
+                let params = Params::new(m_cost, t_cost, p_cost, None)
+                    .map_err(|err| invalid_data(&err.to_string()))?;
+                derived = derive_key(passphrase.expose(), salt, params)?;
+                &derived
+            },
+            (KDF_NONE, _) => return Err(invalid_data("token file requires a key")),
+            (KDF_ARGON2ID, _) => return Err(invalid_data("token file requires a passphrase")),
+            (kdf, _) => return Err(invalid_data(&format!("unsupported key derivation {}", kdf))),
+        };
+
+        let nonce = XNonce::clone_from_slice(reader.take(NONCE_LEN)?);
+        let aad = associated_data(&bytes[..reader.pos], account);
+        let ciphertext = &bytes[reader.pos..];
+
+        XChaCha20Poly1305::new(key)
+            .decrypt(&nonce, Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| invalid_data("token decryption failed"))
+    }
+
+    #[cfg(test)]
+    fn with_params(mut self, params: Params) -> Self {
+        if let KeySource::Passphrase(_, ref mut p) = self.key {
+            *p = params;
+        }
+        self
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> TokenStore<T> for EncryptedFileStore {
+    fn load(&self, account: &str) -> Result<Option<T>, ClientError> {
+        match self.files.read(account)? {
+            Some(bytes) => {
+                let plaintext = Zeroizing::new(self.decrypt(account, &bytes)?);
+                Ok(Some(serde_json::from_slice(&plaintext)?))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, account: &str, token: &T) -> Result<(), ClientError> {
+        let plaintext = Zeroizing::new(serde_json::to_vec(token)?);
+        let bytes = self.encrypt(account, &plaintext)?;
+        self.files.write(account, &bytes)
+    }
+
+    fn delete(&self, account: &str) -> Result<(), ClientError> {
+        self.files.remove(account)
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], io::Error> {
+        if self.bytes.len() - self.pos < len {
+            return Err(invalid_data("truncated token file"));
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn take_u32(&mut self) -> Result<u32, io::Error> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(buf))
+    }
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    params: Params,
+) -> Result<Zeroizing<Key>, io::Error> {
+    let mut key = Zeroizing::new(Key::default());
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| invalid_data(&err.to_string()))?;
+    Ok(key)
+}
+
+fn associated_data(header: &[u8], account: &str) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    aad.extend_from_slice(account.as_bytes());
+    aad
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use argon2::Params;
+
+    use client::{ClientError, ClientErrorKind};
+    use store::TokenStore;
+    use test_support::TempDir;
+    use super::EncryptedFileStore;
+
+    fn passphrase_store(dir: &Path, passphrase: &str) -> EncryptedFileStore {
+        EncryptedFileStore::with_passphrase(dir, passphrase.into())
+            .with_params(Params::new(64, 1, 1, None).unwrap())
+    }
+
+    #[test]
+    fn key_round_trip() {
+        let dir = TempDir::new("encrypted-key");
+        let store = EncryptedFileStore::new(dir.path(), [7; 32]);
+        store.save("foo", &String::from("aaaaaaaa")).unwrap();
+
+        let bytes = fs::read(store.path("foo")).unwrap();
+        assert_eq!(b"ITOK\x01\x00", &bytes[..6]);
+        assert!(!String::from_utf8_lossy(&bytes).contains("aaaaaaaa"));
+
+        assert_eq!(Some(String::from("aaaaaaaa")), store.load("foo").unwrap());
+    }
+
+    #[test]
+    fn passphrase_round_trip() {
+        let dir = TempDir::new("encrypted-passphrase");
+        let store = passphrase_store(dir.path(), "hunter2");
+        store.save("foo", &String::from("aaaaaaaa")).unwrap();
+        assert_eq!(Some(String::from("aaaaaaaa")), store.load("foo").unwrap());
+
+        let wrong = passphrase_store(dir.path(), "hunter3");
+        assert!(TokenStore::<String>::load(&wrong, "foo").is_err());
+    }
+
+    #[test]
+    fn wrong_key() {
+        let dir = TempDir::new("encrypted-wrong-key");
+        EncryptedFileStore::new(dir.path(), [7; 32])
+            .save("foo", &String::from("aaaaaaaa"))
+            .unwrap();
+
+        let store = EncryptedFileStore::new(dir.path(), [8; 32]);
+        match TokenStore::<String>::load(&store, "foo").map_err(ClientError::into_kind) {
+            Err(ClientErrorKind::Io(_)) => {},
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let store = passphrase_store(dir.path(), "hunter2");
+        assert!(TokenStore::<String>::load(&store, "foo").is_err());
+    }
+
+    #[test]
+    fn swapped_account() {
+        let dir = TempDir::new("encrypted-swapped");
+        let store = EncryptedFileStore::new(dir.path(), [7; 32]);
+        store.save("foo", &String::from("aaaaaaaa")).unwrap();
+        fs::rename(store.path("foo"), store.path("bar")).unwrap();
+        assert!(TokenStore::<String>::load(&store, "bar").is_err());
+    }
+
+    #[test]
+    fn excessive_costs() {
+        let dir = TempDir::new("encrypted-costs");
+        let store = passphrase_store(dir.path(), "hunter2");
+        store.save("foo", &String::from("aaaaaaaa")).unwrap();
+
+        let mut bytes = fs::read(store.path("foo")).unwrap();
+        bytes[6..10].copy_from_slice(&u32::MAX.to_be_bytes());
+        fs::write(store.path("foo"), &bytes).unwrap();
+        match TokenStore::<String>::load(&store, "foo").map_err(ClientError::into_kind) {
+            Err(ClientErrorKind::Io(ref err)) => assert!(err.to_string().contains("costs")),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn delete() {
+        let dir = TempDir::new("encrypted-delete");
+        let store = EncryptedFileStore::new(dir.path(), [7; 32]);
+        store.save("foo", &String::from("aaaaaaaa")).unwrap();
+        TokenStore::<String>::delete(&store, "foo").unwrap();
+        assert_eq!(None, TokenStore::<String>::load(&store, "foo").unwrap());
+    }
+}
@@ -0,0 +1,181 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use url::form_urlencoded;
+
+use client::ClientError;
+use store::TokenStore;
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A token store writing each account's token to a JSON file in a directory.
+///
+/// Files are replaced atomically by writing to a temporary file and renaming it into place. On
+/// Unix, the directory is created with mode 0700 and token files with mode 0600.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStore {
+    dir: PathBuf,
+    extension: &'static str,
+}
+
+impl FileStore {
+    /// Creates a store in a directory, which is created when the first token is saved.
+    pub fn new<D: Into<PathBuf>>(dir: D) -> Self {
+        FileStore::with_extension(dir, "json")
+    }
+
+    pub(crate) fn with_extension<D: Into<PathBuf>>(dir: D, extension: &'static str) -> Self {
+        FileStore { dir: dir.into(), extension }
+    }
+
+    /// Returns the directory tokens are stored in.
+    pub fn dir(&self) -> &Path { &self.dir }
+
+    /// Returns the path of the file an account's token is stored in.
+    ///
+    /// Account names are percent-encoded, so they cannot escape the directory.
+    pub fn path(&self, account: &str) -> PathBuf {
+        let name: String = form_urlencoded::byte_serialize(account.as_bytes()).collect();
+        self.dir.join(format!("{}.{}", name, self.extension))
+    }
+
+    /// Reads the contents of an account's token file, if it exists.
+    pub(crate) fn read(&self, account: &str) -> Result<Option<Vec<u8>>, ClientError> {
+        match fs::read(self.path(account)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Atomically replaces the contents of an account's token file.
+    pub(crate) fn write(&self, account: &str, bytes: &[u8]) -> Result<(), ClientError> {
+        create_dir(&self.dir)?;
+
+        let path = self.path(account);
+        let temp = self.dir.join(format!(
+            ".{}.{}.{}.tmp",
+            path.file_name().and_then(|name| name.to_str()).unwrap_or("token"),
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed),
+        ));
+
+        let result = create_file(&temp)
+            .and_then(|mut file| {
+                file.write_all(bytes)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp, &path));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        Ok(result?)
+    }
+
+    /// Removes an account's token file, if it exists.
+    pub(crate) fn remove(&self, account: &str) -> Result<(), ClientError> {
+        match fs::remove_file(self.path(account)) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> TokenStore<T> for FileStore {
+    fn load(&self, account: &str) -> Result<Option<T>, ClientError> {
+        match self.read(account)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, account: &str, token: &T) -> Result<(), ClientError> {
+        let bytes = serde_json::to_vec(token)?;
+        self.write(account, &bytes)
+    }
+
+    fn delete(&self, account: &str) -> Result<(), ClientError> {
+        self.remove(account)
+    }
+}
+
+#[cfg(unix)]
+fn create_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+#[cfg(unix)]
+fn create_file(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)
+}
+
+#[cfg(not(unix))]
+fn create_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use store::TokenStore;
+    use test_support::TempDir;
+    use token::{Bearer, Static};
+    use super::FileStore;
+
+    #[test]
+    fn path_escapes_account() {
+        let store = FileStore::new("/tmp/tokens");
+        assert_eq!(
+            "/tmp/tokens/..%2Ffoo%40example.com.json",
+            store.path("../foo@example.com").to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn save_load_delete() {
+        let dir = TempDir::new("file-store");
+        let store = FileStore::new(dir.path());
+        let token: Bearer<Static> = ::serde_json::from_str(
+            r#"{"access_token":"aaaaaaaa","scope":null,"lifetime":null}"#
+        ).unwrap();
+
+        assert_eq!(None, TokenStore::<Bearer<Static>>::load(&store, "foo").unwrap());
+        store.save("foo", &token).unwrap();
+        assert_eq!(Some(token.clone()), store.load("foo").unwrap());
+
+        TokenStore::<Bearer<Static>>::delete(&store, "foo").unwrap();
+        assert_eq!(None, TokenStore::<Bearer<Static>>::load(&store, "foo").unwrap());
+        TokenStore::<Bearer<Static>>::delete(&store, "foo").unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_permissions() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("file-store-permissions");
+        let store = FileStore::new(dir.path());
+        store.save("foo", &String::from("aaaaaaaa")).unwrap();
+        store.save("foo", &String::from("bbbbbbbb")).unwrap();
+
+        let mode = fs::metadata(store.path("foo")).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+        let mode = fs::metadata(store.dir()).unwrap().permissions().mode();
+        assert_eq!(0o700, mode & 0o777);
+        assert_eq!(1, fs::read_dir(store.dir()).unwrap().count());
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use client::ClientError;
+use store::TokenStore;
+
+/// An in-memory token store.
+#[derive(Debug)]
+pub struct MemoryStore<T> {
+    tokens: Mutex<HashMap<String, T>>,
+}
+
+impl<T> MemoryStore<T> {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        MemoryStore { tokens: Mutex::new(HashMap::new()) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, T>> {
+        self.tokens.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> Default for MemoryStore<T> {
+    fn default() -> Self { MemoryStore::new() }
+}
+
+impl<T: Clone> TokenStore<T> for MemoryStore<T> {
+    fn load(&self, account: &str) -> Result<Option<T>, ClientError> {
+        Ok(self.lock().get(account).cloned())
+    }
+
+    fn save(&self, account: &str, token: &T) -> Result<(), ClientError> {
+        self.lock().insert(account.to_owned(), token.clone());
+        Ok(())
+    }
+
+    fn delete(&self, account: &str) -> Result<(), ClientError> {
+        self.lock().remove(account);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use store::TokenStore;
+    use super::MemoryStore;
+
+    #[test]
+    fn save_load_delete() {
+        let store = MemoryStore::new();
+        assert_eq!(None, store.load("foo").unwrap());
+
+        store.save("foo", &String::from("aaaaaaaa")).unwrap();
+        store.save("bar", &String::from("bbbbbbbb")).unwrap();
+        assert_eq!(Some(String::from("aaaaaaaa")), store.load("foo").unwrap());
+
+        store.delete("foo").unwrap();
+        assert_eq!(None, store.load("foo").unwrap());
+        assert_eq!(Some(String::from("bbbbbbbb")), store.load("bar").unwrap());
+    }
+}
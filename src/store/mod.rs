@@ -0,0 +1,43 @@
+//! Token storage.
+//!
+//! Tokens are persisted through the `TokenStore` trait, keyed by an account name chosen by the
+//! application. `MemoryStore` keeps tokens for the life of the process, while `FileStore` writes
+//! each token to its own JSON file. `EncryptedFileStore` encrypts those files at rest.
+
+mod encrypted;
+mod file;
+mod memory;
+
+pub use self::encrypted::EncryptedFileStore;
+pub use self::file::FileStore;
+pub use self::memory::MemoryStore;
+
+use std::sync::Arc;
+
+use client::ClientError;
+
+/// Token storage.
+pub trait TokenStore<T> {
+    /// Loads the token saved for an account, if any.
+    fn load(&self, account: &str) -> Result<Option<T>, ClientError>;
+
+    /// Saves a token for an account, replacing any previous token.
+    fn save(&self, account: &str, token: &T) -> Result<(), ClientError>;
+
+    /// Deletes the token saved for an account, if any.
+    fn delete(&self, account: &str) -> Result<(), ClientError>;
+}
+
+impl<T, S: TokenStore<T> + ?Sized> TokenStore<T> for Arc<S> {
+    fn load(&self, account: &str) -> Result<Option<T>, ClientError> {
+        (**self).load(account)
+    }
+
+    fn save(&self, account: &str, token: &T) -> Result<(), ClientError> {
+        (**self).save(account, token)
+    }
+
+    fn delete(&self, account: &str) -> Result<(), ClientError> {
+        (**self).delete(account)
+    }
+}
@@ -0,0 +1,278 @@
+//! Stand-in HTTP server and fixtures for tests.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::marker::PhantomData;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::{self, Value};
+use url::Url;
+
+use client::response::{FromResponse, ParsePolicy};
+use client::ClientError;
+use provider::Provider;
+use store::{MemoryStore, TokenStore};
+use token::{Lifetime, Token};
+
+/// A request received by the server.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|header| header.0.eq_ignore_ascii_case(name))
+            .map(|header| &header.1[..])
+    }
+}
+
+/// A canned response.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(status: u16, body: &str) -> Self {
+        Response {
+            status,
+            headers: vec![(String::from("Content-Type"), String::from("application/json"))],
+            body: body.into(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Replaces any headers with the same name.
+    pub fn set_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.header(name, value)
+    }
+}
+
+/// Server answering every request using a handler.
+#[derive(Debug)]
+pub struct Server {
+    url: Url,
+    hits: Arc<AtomicUsize>,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Server {
+    pub fn new<F>(handler: F) -> Self
+    where F: Fn(usize, &Request) -> Response + Send + Sync + 'static {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let hits = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+
+        let thread_hits = hits.clone();
+        let thread_requests = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let hits = thread_hits.clone();
+                let requests = thread_requests.clone();
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    let request = match read_request(&mut stream) {
+                        Some(request) => request,
+                        None => return,
+                    };
+                    let n = hits.fetch_add(1, Ordering::SeqCst);
+                    requests.lock().unwrap().push(request.clone());
+                    let response = handler(n, &request);
+                    let _ = write_response(&mut stream, &response);
+                });
+            }
+        });
+
+        Server { url, hits, requests }
+    }
+
+    /// Returns a URL on the server.
+    pub fn url(&self, path: &str) -> Url {
+        self.url.join(path).unwrap()
+    }
+
+    /// Returns the number of requests received.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::SeqCst)
+    }
+
+    /// Returns the requests received.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request<S: Read>(stream: S) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_owned();
+    let path = parts.next()?.to_owned();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() { break; }
+        let mut split = line.splitn(2, ':');
+        let name = split.next()?.trim().to_owned();
+        let value = split.next().unwrap_or("").trim().to_owned();
+        headers.push((name, value));
+    }
+
+    let length = headers.iter()
+        .find(|header| header.0.eq_ignore_ascii_case("content-length"))
+        .and_then(|header| header.1.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+
+    Some(Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+fn write_response<S: Write>(mut stream: S, response: &Response) -> ::std::io::Result<()> {
+    write!(stream, "HTTP/1.1 {} Status\r\n", response.status)?;
+    for (name, value) in &response.headers {
+        write!(stream, "{}: {}\r\n", name, value)?;
+    }
+    write!(stream, "Content-Length: {}\r\n", response.body.len())?;
+    write!(stream, "Connection: close\r\n\r\n")?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
+
+/// Returns an unsigned JWT with the given claims.
+pub fn jwt(claims: &str) -> String {
+    format!(
+        "{}.{}.c2lnbmF0dXJl",
+        URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256","typ":"at+jwt"}"#),
+        URL_SAFE_NO_PAD.encode(claims),
+    )
+}
+
+/// Returns a token of type `T` parsed from a response issuing a refresh token.
+pub fn token_with_refresh<T: FromResponse>(
+    token_type: &str,
+    access_token: &str,
+    refresh_token: &str,
+    expires_in: i64,
+) -> T {
+    let json = json!({
+        "token_type": token_type,
+        "access_token": access_token,
+        "expires_in": expires_in,
+        "refresh_token": refresh_token,
+    });
+    T::from_response(&json).unwrap()
+}
+
+/// Returns the claims of the DPoP proof sent with a request.
+pub fn proof_claims(request: &Request) -> Value {
+    let proof = request.header("DPoP").unwrap();
+    let claims = proof.split('.').nth(1).unwrap();
+    serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).unwrap()).unwrap()
+}
+
+/// Provider issuing tokens of type `T` with lifetime `L`.
+#[derive(Debug)]
+pub struct TestProvider<T, L> {
+    pub auth_uri: Url,
+    pub token_uri: Url,
+    pub policy: ParsePolicy,
+    token: PhantomData<fn() -> (T, L)>,
+}
+
+impl<T, L> TestProvider<T, L> {
+    pub fn new(auth_uri: Url, token_uri: Url) -> Self {
+        TestProvider { auth_uri, token_uri, policy: ParsePolicy::default(), token: PhantomData }
+    }
+
+    /// Creates a provider whose endpoints are on a server.
+    pub fn on(server: &Server) -> Self {
+        TestProvider::new(server.url("/auth"), server.url("/token"))
+    }
+}
+
+impl<T: Token<L>, L: Lifetime> Provider for TestProvider<T, L> {
+    type Lifetime = L;
+    type Token = T;
+    fn auth_uri(&self) -> &Url { &self.auth_uri }
+    fn token_uri(&self) -> &Url { &self.token_uri }
+    fn parse_policy(&self) -> ParsePolicy { self.policy }
+}
+
+/// Temporary directory, removed when dropped.
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Returns an empty directory path unique to the test and process. The directory itself is
+    /// not created.
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("inth-oauth2-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path { &self.0 }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Store whose saves fail.
+#[derive(Debug)]
+pub struct ReadOnlyStore<T>(pub MemoryStore<T>);
+
+impl<T: Clone> TokenStore<T> for ReadOnlyStore<T> {
+    fn load(&self, account: &str) -> Result<Option<T>, ClientError> {
+        self.0.load(account)
+    }
+
+    fn save(&self, _: &str, _: &T) -> Result<(), ClientError> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "read-only store").into())
+    }
+
+    fn delete(&self, account: &str) -> Result<(), ClientError> {
+        self.0.delete(account)
+    }
+}
@@ -0,0 +1,248 @@
+use serde::de::{Deserialize, Deserializer, Error as DeError};
+use serde::ser::{Serialize, Serializer};
+use serde_json::Value;
+
+use client::response::{expect_object, str_field, FromResponse, ParseError, ParsePolicy};
+use secret::Secret;
+use token::{Bearer, DPoP, Lifetime, StorableLifetime, StoredToken, Token, TokenType};
+
+/// A token of any type, determined by the `token_type` of the response.
+///
+/// Useful with providers which issue several token types, such as DPoP-capable servers which may
+/// fall back to Bearer tokens, or token exchange, which issues `N_A` tokens. Tokens of types
+/// without a dedicated implementation are parsed as `Other`, and cannot be presented in the
+/// `Authorization` header.
+///
+/// Tokens serialize as a `StoredToken`, so `Bearer` and `DPoP` tokens saved on their own can be
+/// deserialized as `AnyToken`. Unversioned tokens saved by 0.16 are deserialized as Bearer tokens.
+///
+/// See [RFC 6749, section 7.1](http://tools.ietf.org/html/rfc6749#section-7.1).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnyToken<L: Lifetime> {
+    /// A Bearer token.
+    Bearer(Bearer<L>),
+
+    /// A DPoP token.
+    DPoP(DPoP<L>),
+
+    /// A token of another type.
+    Other {
+        /// The `token_type` of the response.
+        token_type: String,
+        /// The access token.
+        access_token: Secret,
+        /// The scope, if available.
+        scope: Option<String>,
+        /// The token lifetime.
+        lifetime: L,
+    },
+}
+
+impl<L: Lifetime> Token<L> for AnyToken<L> {
+    fn access_token(&self) -> &str {
+        match *self {
+            AnyToken::Bearer(ref token) => token.access_token(),
+            AnyToken::DPoP(ref token) => token.access_token(),
+            AnyToken::Other { ref access_token, .. } => access_token.expose(),
+        }
+    }
+    fn scope(&self) -> Option<&str> {
+        match *self {
+            AnyToken::Bearer(ref token) => token.scope(),
+            AnyToken::DPoP(ref token) => token.scope(),
+            AnyToken::Other { ref scope, .. } => scope.as_ref().map(|s| &s[..]),
+        }
+    }
+    fn lifetime(&self) -> &L {
+        match *self {
+            AnyToken::Bearer(ref token) => token.lifetime(),
+            AnyToken::DPoP(ref token) => token.lifetime(),
+            AnyToken::Other { ref lifetime, .. } => lifetime,
+        }
+    }
+    fn token_type(&self) -> TokenType {
+        match *self {
+            AnyToken::Bearer(_) => TokenType::Bearer,
+            AnyToken::DPoP(_) => TokenType::DPoP,
+            AnyToken::Other { ref token_type, .. } => TokenType::from(&token_type[..]),
+        }
+    }
+}
+
+impl<L: Lifetime> AnyToken<L> {
+    fn from_response_and_lifetime(
+        json: &Value,
+        lifetime: L,
+        policy: &ParsePolicy,
+    ) -> Result<Self, ParseError> {
+        let obj = expect_object(json)?;
+
+        let token_type = str_field(obj, "token_type")?;
+
+        match TokenType::from(token_type) {
+            TokenType::Bearer =>
+                Bearer::from_response_and_lifetime(json, lifetime, policy).map(AnyToken::Bearer),
+            TokenType::DPoP =>
+                DPoP::from_response_and_lifetime(json, lifetime, policy).map(AnyToken::DPoP),
+            _ => {
+                let access_token = str_field(obj, "access_token")?;
+                let scope = obj.get("scope").and_then(Value::as_str);
+                Ok(AnyToken::Other {
+                    token_type: token_type.into(),
+                    access_token: access_token.into(),
+                    scope: scope.map(Into::into),
+                    lifetime,
+                })
+            },
+        }
+    }
+}
+
+impl<L: StorableLifetime> AnyToken<L> {
+    /// Converts the token to the versioned serialization format.
+    pub fn to_stored(&self) -> StoredToken {
+        match *self {
+            AnyToken::Bearer(ref token) => token.to_stored(),
+            AnyToken::DPoP(ref token) => token.to_stored(),
+            AnyToken::Other { ref token_type, ref access_token, ref scope, ref lifetime } => {
+                StoredToken::new(
+                    &TokenType::from(&token_type[..]),
+                    access_token.clone(),
+                    scope.clone(),
+                    lifetime.to_stored(),
+                )
+            },
+        }
+    }
+
+    /// Converts the token from the versioned serialization format.
+    pub fn from_stored(stored: StoredToken) -> Result<Self, ParseError> {
+        match stored.token_type() {
+            TokenType::Bearer => Bearer::from_stored(stored).map(AnyToken::Bearer),
+            TokenType::DPoP => DPoP::from_stored(stored).map(AnyToken::DPoP),
+            _ => Ok(AnyToken::Other {
+                token_type: stored.token_type,
+                access_token: stored.access_token,
+                scope: stored.scope,
+                lifetime: L::from_stored(stored.lifetime)?,
+            }),
+        }
+    }
+}
+
+impl<L: StorableLifetime> Serialize for AnyToken<L> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_stored().serialize(serializer)
+    }
+}
+
+impl<'de, L: StorableLifetime> Deserialize<'de> for AnyToken<L> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredToken::from_deserializer(deserializer, &TokenType::Bearer)?;
+        AnyToken::from_stored(stored).map_err(D::Error::custom)
+    }
+}
+
+impl<L: Lifetime> FromResponse for AnyToken<L> {
+    fn from_response(json: &Value) -> Result<Self, ParseError> {
+        AnyToken::from_response_with(json, &ParsePolicy::default())
+    }
+
+    fn from_response_inherit(json: &Value, prev: &Self) -> Result<Self, ParseError> {
+        AnyToken::from_response_inherit_with(json, prev, &ParsePolicy::default())
+    }
+
+    fn from_response_with(json: &Value, policy: &ParsePolicy) -> Result<Self, ParseError> {
+        let lifetime = FromResponse::from_response_with(json, policy)?;
+        AnyToken::from_response_and_lifetime(json, lifetime, policy)
+    }
+
+    fn from_response_inherit_with(
+        json: &Value,
+        prev: &Self,
+        policy: &ParsePolicy,
+    ) -> Result<Self, ParseError> {
+        let lifetime = FromResponse::from_response_inherit_with(json, prev.lifetime(), policy)?;
+        AnyToken::from_response_and_lifetime(json, lifetime, policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use client::response::{FromResponse, ParseError};
+    use token::{Bearer, Static, Token, TokenType};
+    use super::AnyToken;
+
+    fn parse(json: &str) -> Result<AnyToken<Static>, ParseError> {
+        AnyToken::from_response(&json.parse().unwrap())
+    }
+
+    #[test]
+    fn from_response_bearer() {
+        let token = parse(r#"{"token_type":"bearer","access_token":"aaaaaaaa"}"#).unwrap();
+        match token {
+            AnyToken::Bearer(_) => {},
+            token => panic!("unexpected token {:?}", token),
+        }
+        assert_eq!(TokenType::Bearer, token.token_type());
+        assert_eq!(Some(String::from("Bearer aaaaaaaa")), token.authorization_header());
+    }
+
+    #[test]
+    fn from_response_dpop() {
+        let token = parse(r#"{"token_type":"DPoP","access_token":"aaaaaaaa"}"#).unwrap();
+        assert_eq!(TokenType::DPoP, token.token_type());
+        assert_eq!(Some(String::from("DPoP aaaaaaaa")), token.authorization_header());
+    }
+
+    #[test]
+    fn from_response_other() {
+        let token = parse(r#"{"token_type":"N_A","access_token":"aaaaaaaa","scope":"foo"}"#)
+            .unwrap();
+        assert_eq!(TokenType::NotApplicable, token.token_type());
+        assert_eq!("aaaaaaaa", token.access_token());
+        assert_eq!(Some("foo"), token.scope());
+        assert_eq!(None, token.authorization_header());
+    }
+
+    #[test]
+    fn from_response_without_token_type() {
+        assert_eq!(
+            ParseError::expected_field_type("token_type", "string"),
+            parse(r#"{"access_token":"aaaaaaaa"}"#).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        for json in &[
+            r#"{"token_type":"Bearer","access_token":"aaaaaaaa"}"#,
+            r#"{"token_type":"DPoP","access_token":"aaaaaaaa"}"#,
+            r#"{"token_type":"N_A","access_token":"aaaaaaaa","scope":"foo"}"#,
+        ] {
+            let token = parse(json).unwrap();
+            let serialized = serde_json::to_string(&token).unwrap();
+            assert_eq!(token, serde_json::from_str(&serialized).unwrap());
+        }
+    }
+
+    #[test]
+    fn deserialize_0_16() {
+        let token: AnyToken<Static> =
+            serde_json::from_str(r#"{"access_token":"aaaaaaaa","scope":null,"lifetime":null}"#)
+                .unwrap();
+        assert_eq!(TokenType::Bearer, token.token_type());
+    }
+
+    #[test]
+    fn deserialize_bearer() {
+        let bearer = Bearer::<Static>::from_response(
+            &r#"{"token_type":"Bearer","access_token":"aaaaaaaa"}"#.parse().unwrap()
+        ).unwrap();
+        let token: AnyToken<Static> =
+            serde_json::from_str(&serde_json::to_string(&bearer).unwrap()).unwrap();
+        assert_eq!(AnyToken::Bearer(bearer), token);
+    }
+}
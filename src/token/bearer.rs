@@ -1,21 +1,78 @@
-use serde_json::Value;
+use std::fmt;
 
-use client::response::{FromResponse, ParseError};
-use token::{Token, Lifetime};
+use reqwest::header::CACHE_CONTROL;
+use reqwest::RequestBuilder;
+use serde::de::{Deserialize, DeserializeOwned, Deserializer, Error as DeError};
+use serde::ser::{Serialize, Serializer};
+use serde_json::{self, Map, Value};
+
+use client::response::{expect_object, str_field, FromResponse, ParseError, ParsePolicy};
+use secret::{RedactedFields, Secret};
+use token::{Token, Lifetime, StorableLifetime, StoredToken, TokenType};
+
+/// Methods of sending bearer access tokens in resource requests.
+///
+/// See [RFC 6750, section 2](http://tools.ietf.org/html/rfc6750#section-2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Transmission {
+    /// The `Authorization` request header field.
+    ///
+    /// See [RFC 6750, section 2.1](http://tools.ietf.org/html/rfc6750#section-2.1).
+    #[default]
+    Header,
+
+    /// The `access_token` form-encoded body parameter.
+    ///
+    /// See [RFC 6750, section 2.2](http://tools.ietf.org/html/rfc6750#section-2.2).
+    FormBody,
+
+    /// The `access_token` URI query parameter.
+    ///
+    /// See [RFC 6750, section 2.3](http://tools.ietf.org/html/rfc6750#section-2.3).
+    Query,
+}
+
+/// Token response fields modelled by `Bearer` and its lifetimes.
+const MODELLED_FIELDS: &[&str] = &[
+    "access_token",
+    "token_type",
+    "expires_in",
+    "refresh_token",
+    "refresh_token_expires_in",
+    "scope",
+];
 
 /// The bearer token type.
 ///
+/// Fields of the token response which are not otherwise modelled, such as `id_token`, are retained
+/// and serialized along with the token.
+///
+/// Bearer tokens serialize as a `StoredToken`. `Debug` redacts the access token and additional
+/// fields which might hold secrets, such as `id_token`.
+///
 /// See [RFC 6750](http://tools.ietf.org/html/rfc6750).
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct Bearer<L: Lifetime> {
-    access_token: String,
+    access_token: Secret,
     scope: Option<String>,
     lifetime: L,
+    extra: Map<String, Value>,
+}
+
+impl<L: Lifetime + fmt::Debug> fmt::Debug for Bearer<L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("Bearer")
+            .field("access_token", &self.access_token)
+            .field("scope", &self.scope)
+            .field("lifetime", &self.lifetime)
+            .field("extra", &RedactedFields(&self.extra))
+            .finish()
+    }
 }
 
 impl<L: Lifetime> Token<L> for Bearer<L> {
     fn access_token(&self) -> &str {
-        &self.access_token
+        self.access_token.expose()
     }
     fn scope(&self) -> Option<&str> {
         self.scope.as_ref().map(|s| &s[..])
@@ -23,41 +80,178 @@ impl<L: Lifetime> Token<L> for Bearer<L> {
     fn lifetime(&self) -> &L {
         &self.lifetime
     }
+    fn token_type(&self) -> TokenType {
+        TokenType::Bearer
+    }
 }
 
 impl<L: Lifetime> Bearer<L> {
-    fn from_response_and_lifetime(json: &Value, lifetime: L) -> Result<Self, ParseError> {
-        let obj = json.as_object().ok_or(ParseError::ExpectedType("object"))?;
-
-        let token_type = obj.get("token_type")
-            .and_then(Value::as_str)
-            .ok_or(ParseError::ExpectedFieldType("token_type", "string"))?;
-        if token_type != "Bearer" && token_type != "bearer" {
-            return Err(ParseError::ExpectedFieldValue("token_type", "Bearer"));
+    /// Returns the additional fields of the token response.
+    ///
+    /// See [RFC 6749, section 5.1](http://tools.ietf.org/html/rfc6749#section-5.1).
+    pub fn extra(&self) -> &Map<String, Value> { &self.extra }
+
+    /// Returns an additional field of the token response.
+    pub fn extra_field(&self, name: &str) -> Option<&Value> { self.extra.get(name) }
+
+    /// Returns an additional string field of the token response.
+    pub fn extra_str(&self, name: &str) -> Option<&str> {
+        self.extra_field(name).and_then(Value::as_str)
+    }
+
+    /// Returns an additional integer field of the token response.
+    pub fn extra_i64(&self, name: &str) -> Option<i64> {
+        self.extra_field(name).and_then(Value::as_i64)
+    }
+
+    /// Deserializes an additional field of the token response.
+    ///
+    /// Returns `None` if the field is absent.
+    pub fn extra_as<T: DeserializeOwned>(&self, name: &str) -> Option<Result<T, serde_json::Error>> {
+        self.extra_field(name).map(|value| serde_json::from_value(value.clone()))
+    }
+
+    /// Returns the OpenID Connect ID token, if the token response included one.
+    ///
+    /// The ID token is not validated.
+    ///
+    /// See [OpenID Connect Core 1.0, section 3.1.3.3](https://openid.net/specs/openid-connect-core-1_0.html#TokenResponse).
+    pub fn id_token(&self) -> Option<&str> { self.extra_str("id_token") }
+
+    /// Sends the access token in the `Authorization` header of a request.
+    ///
+    /// See [RFC 6750, section 2.1](http://tools.ietf.org/html/rfc6750#section-2.1).
+    pub fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        request.bearer_auth(self.access_token.expose())
+    }
+
+    /// Sends the access token in a request using a transmission method.
+    ///
+    /// The form body method replaces the request body with one containing only the access token.
+    /// Use `authorize_form` to send other parameters along with it.
+    pub fn authorize_with(
+        &self,
+        request: RequestBuilder,
+        transmission: Transmission,
+    ) -> RequestBuilder {
+        match transmission {
+            Transmission::Header => self.authorize(request),
+            Transmission::FormBody => self.authorize_form(request, &[]),
+            Transmission::Query => self.authorize_query(request),
         }
+    }
+
+    /// Sends the access token as a form-encoded body parameter along with other parameters.
+    ///
+    /// The request method must not be `GET`.
+    ///
+    /// See [RFC 6750, section 2.2](http://tools.ietf.org/html/rfc6750#section-2.2).
+    pub fn authorize_form(&self, request: RequestBuilder, params: &[(&str, &str)]) -> RequestBuilder {
+        let mut form = params.to_vec();
+        form.push(("access_token", self.access_token.expose()));
+        request.form(&form)
+    }
+
+    /// Sends the access token as a URI query parameter.
+    ///
+    /// Since the access token may be logged or cached along with the URI, this method is
+    /// discouraged and should only be used with resource servers which support no other. The
+    /// request is marked `Cache-Control: no-store`.
+    ///
+    /// See [RFC 6750, section 2.3](http://tools.ietf.org/html/rfc6750#section-2.3).
+    pub fn authorize_query(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+            .query(&[("access_token", self.access_token.expose())])
+            .header(CACHE_CONTROL, "no-store")
+    }
+
+    pub(crate) fn from_response_and_lifetime(
+        json: &Value,
+        lifetime: L,
+        policy: &ParsePolicy,
+    ) -> Result<Self, ParseError> {
+        let obj = expect_object(json)?;
 
-        let access_token = obj.get("access_token")
-            .and_then(Value::as_str)
-            .ok_or(ParseError::ExpectedFieldType("access_token", "string"))?;
+        policy.check_token_type(obj, "Bearer")?;
+
+        let access_token = str_field(obj, "access_token")?;
         let scope = obj.get("scope").and_then(Value::as_str);
+        let extra = obj.iter()
+            .filter(|(key, _)| !MODELLED_FIELDS.contains(&&key[..]))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
 
         Ok(Bearer {
             access_token: access_token.into(),
             scope: scope.map(Into::into),
-            lifetime: lifetime,
+            lifetime,
+            extra,
+        })
+    }
+}
+
+impl<L: StorableLifetime> Bearer<L> {
+    /// Converts the token to the versioned serialization format.
+    pub fn to_stored(&self) -> StoredToken {
+        let mut stored = StoredToken::new(
+            &TokenType::Bearer,
+            self.access_token.clone(),
+            self.scope.clone(),
+            self.lifetime.to_stored(),
+        );
+        stored.extra = self.extra.clone();
+        stored
+    }
+
+    /// Converts the token from the versioned serialization format.
+    ///
+    /// Tokens stored with a different lifetime type can be converted if this lifetime can hold
+    /// the stored fields.
+    pub fn from_stored(stored: StoredToken) -> Result<Self, ParseError> {
+        stored.expect_type(&TokenType::Bearer)?;
+        Ok(Bearer {
+            access_token: stored.access_token,
+            scope: stored.scope,
+            lifetime: L::from_stored(stored.lifetime)?,
+            extra: stored.extra,
         })
     }
 }
 
+impl<L: StorableLifetime> Serialize for Bearer<L> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_stored().serialize(serializer)
+    }
+}
+
+impl<'de, L: StorableLifetime> Deserialize<'de> for Bearer<L> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredToken::from_deserializer(deserializer, &TokenType::Bearer)?;
+        Bearer::from_stored(stored).map_err(D::Error::custom)
+    }
+}
+
 impl<L: Lifetime> FromResponse for Bearer<L> {
     fn from_response(json: &Value) -> Result<Self, ParseError> {
-        let lifetime = FromResponse::from_response(json)?;
-        Bearer::from_response_and_lifetime(json, lifetime)
+        Bearer::from_response_with(json, &ParsePolicy::default())
     }
 
     fn from_response_inherit(json: &Value, prev: &Self) -> Result<Self, ParseError> {
-        let lifetime = FromResponse::from_response_inherit(json, &prev.lifetime)?;
-        Bearer::from_response_and_lifetime(json, lifetime)
+        Bearer::from_response_inherit_with(json, prev, &ParsePolicy::default())
+    }
+
+    fn from_response_with(json: &Value, policy: &ParsePolicy) -> Result<Self, ParseError> {
+        let lifetime = FromResponse::from_response_with(json, policy)?;
+        Bearer::from_response_and_lifetime(json, lifetime, policy)
+    }
+
+    fn from_response_inherit_with(
+        json: &Value,
+        prev: &Self,
+        policy: &ParsePolicy,
+    ) -> Result<Self, ParseError> {
+        let lifetime = FromResponse::from_response_inherit_with(json, &prev.lifetime, policy)?;
+        Bearer::from_response_and_lifetime(json, lifetime, policy)
     }
 }
 
@@ -65,15 +259,87 @@ impl<L: Lifetime> FromResponse for Bearer<L> {
 mod tests {
     use chrono::{Utc, Duration};
 
-    use client::response::{FromResponse, ParseError};
-    use token::{Static, Refresh};
-    use super::Bearer;
+    use reqwest;
+    use serde_json::{self, Map};
+
+    use client::response::{FromResponse, ParseError, ParsePolicy};
+    use secret::Secret;
+    use test_support::{Response, Server};
+    use token::{Static, Refresh, Token};
+    use super::{Bearer, Transmission};
+
+    fn bearer() -> Bearer<Static> {
+        Bearer {
+            access_token: Secret::from("aaaaaaaa"),
+            scope: None,
+            lifetime: Static,
+            extra: Map::new(),
+        }
+    }
+
+    fn send(transmission: Transmission) -> ::test_support::Request {
+        let server = Server::new(|_, _: &::test_support::Request| Response::json(200, "{}"));
+        let request = reqwest::Client::new().post(server.url("/resource?foo=bar"));
+        bearer().authorize_with(request, transmission).send().unwrap();
+        server.requests().remove(0)
+    }
+
+    #[test]
+    fn authorize_header() {
+        let request = send(Transmission::default());
+        assert_eq!(Some("Bearer aaaaaaaa"), request.header("Authorization"));
+        assert_eq!("/resource?foo=bar", request.path);
+        assert_eq!("", request.body);
+    }
+
+    #[test]
+    fn authorize_form_body() {
+        let request = send(Transmission::FormBody);
+        assert_eq!(None, request.header("Authorization"));
+        assert_eq!(Some("application/x-www-form-urlencoded"), request.header("Content-Type"));
+        assert_eq!("access_token=aaaaaaaa", request.body);
+    }
+
+    #[test]
+    fn authorize_form_with_params() {
+        let server = Server::new(|_, _: &::test_support::Request| Response::json(200, "{}"));
+        let request = reqwest::Client::new().post(server.url("/resource"));
+        bearer().authorize_form(request, &[("foo", "bar")]).send().unwrap();
+        assert_eq!("foo=bar&access_token=aaaaaaaa", server.requests()[0].body);
+    }
+
+    #[test]
+    fn authorize_query() {
+        let request = send(Transmission::Query);
+        assert_eq!(None, request.header("Authorization"));
+        assert_eq!(Some("no-store"), request.header("Cache-Control"));
+        assert_eq!("/resource?foo=bar&access_token=aaaaaaaa", request.path);
+    }
+
+    #[test]
+    fn debug_redacts_access_token() {
+        let debug = format!("{:?}", bearer());
+        assert!(!debug.contains("aaaaaaaa"));
+        assert!(debug.contains("sha256:"));
+    }
+
+    #[test]
+    fn debug_redacts_extra_secrets() {
+        let json = r#"
+            {"token_type":"Bearer","access_token":"aaaaaaaa","id_token":"eyJhbGciOiJub25lIn0","user_id":42}
+        "#.parse().unwrap();
+        let bearer = Bearer::<Static>::from_response(&json).unwrap();
+        let debug = format!("{:?}", bearer);
+        assert!(!debug.contains("eyJhbGciOiJub25lIn0"), "{}", debug);
+        assert!(debug.contains("user_id"));
+        assert!(!format!("{:?}", bearer.to_stored()).contains("eyJhbGciOiJub25lIn0"));
+    }
 
     #[test]
     fn from_response_with_invalid_token_type() {
         let json = r#"{"token_type":"MAC","access_token":"aaaaaaaa"}"#.parse().unwrap();
         assert_eq!(
-            ParseError::ExpectedFieldValue("token_type", "Bearer"),
+            ParseError::expected_field_value("token_type", "Bearer"),
             Bearer::<Static>::from_response(&json).unwrap_err()
         );
     }
@@ -83,9 +349,10 @@ mod tests {
         let json = r#"{"token_type":"Bearer","access_token":"aaaaaaaa"}"#.parse().unwrap();
         assert_eq!(
             Bearer {
-                access_token: String::from("aaaaaaaa"),
+                access_token: Secret::from("aaaaaaaa"),
                 scope: None,
                 lifetime: Static,
+                extra: Map::new(),
             },
             Bearer::<Static>::from_response(&json).unwrap()
         );
@@ -96,14 +363,28 @@ mod tests {
         let json = r#"{"token_type":"bearer","access_token":"aaaaaaaa"}"#.parse().unwrap();
         assert_eq!(
             Bearer {
-                access_token: String::from("aaaaaaaa"),
+                access_token: Secret::from("aaaaaaaa"),
                 scope: None,
                 lifetime: Static,
+                extra: Map::new(),
             },
             Bearer::<Static>::from_response(&json).unwrap()
         );
     }
 
+    #[test]
+    fn from_response_upper_case() {
+        let json = r#"{"token_type":"BEARER","access_token":"aaaaaaaa"}"#.parse().unwrap();
+        assert_eq!(
+            ParseError::expected_field_value("token_type", "Bearer"),
+            Bearer::<Static>::from_response(&json).unwrap_err()
+        );
+        assert_eq!(
+            bearer(),
+            Bearer::<Static>::from_response_with(&json, &ParsePolicy::lenient()).unwrap()
+        );
+    }
+
     #[test]
     fn from_response_with_scope() {
         let json = r#"{"token_type":"Bearer","access_token":"aaaaaaaa","scope":"foo"}"#
@@ -111,9 +392,10 @@ mod tests {
             .unwrap();
         assert_eq!(
             Bearer {
-                access_token: String::from("aaaaaaaa"),
+                access_token: Secret::from("aaaaaaaa"),
                 scope: Some(String::from("foo")),
                 lifetime: Static,
+                extra: Map::new(),
             },
             Bearer::<Static>::from_response(&json).unwrap()
         );
@@ -130,7 +412,7 @@ mod tests {
             }
         "#.parse().unwrap();
         let bearer = Bearer::<Refresh>::from_response(&json).unwrap();
-        assert_eq!("aaaaaaaa", bearer.access_token);
+        assert_eq!("aaaaaaaa", bearer.access_token());
         assert_eq!(None, bearer.scope);
         let refresh = bearer.lifetime;
         assert_eq!("bbbbbbbb", refresh.refresh_token());
@@ -158,11 +440,72 @@ mod tests {
             }
         "#.parse().unwrap();
         let bearer = Bearer::<Refresh>::from_response_inherit(&json, &prev).unwrap();
-        assert_eq!("cccccccc", bearer.access_token);
+        assert_eq!("cccccccc", bearer.access_token());
         assert_eq!(None, bearer.scope);
         let refresh = bearer.lifetime;
         assert_eq!("bbbbbbbb", refresh.refresh_token());
         assert!(refresh.expires() > &Utc::now());
         assert!(refresh.expires() <= &(Utc::now() + Duration::seconds(3600)));
     }
+
+    #[test]
+    fn from_response_extra() {
+        let json = r#"
+            {
+                "token_type":"Bearer",
+                "access_token":"aaaaaaaa",
+                "id_token":"eyJhbGciOiJSUzI1NiJ9",
+                "instance_url":"https://example.my.salesforce.com",
+                "user":{"id":7,"name":"foo"}
+            }
+        "#.parse().unwrap();
+        let bearer = Bearer::<Static>::from_response(&json).unwrap();
+        assert_eq!(3, bearer.extra().len());
+        assert!(bearer.extra_field("access_token").is_none());
+        assert_eq!(Some("eyJhbGciOiJSUzI1NiJ9"), bearer.id_token());
+        assert_eq!(Some("https://example.my.salesforce.com"), bearer.extra_str("instance_url"));
+        assert_eq!(None, bearer.extra_i64("instance_url"));
+
+        #[derive(Deserialize)]
+        struct User { id: i64, name: String }
+        let user: User = bearer.extra_as("user").unwrap().unwrap();
+        assert_eq!((7, String::from("foo")), (user.id, user.name));
+        assert!(bearer.extra_as::<User>("instance_url").unwrap().is_err());
+        assert!(bearer.extra_as::<User>("account_id").is_none());
+    }
+
+    #[test]
+    fn from_response_modelled_fields() {
+        let json = r#"
+            {
+                "token_type":"Bearer",
+                "access_token":"aaaaaaaa",
+                "expires_in":3600,
+                "refresh_token":"bbbbbbbb",
+                "refresh_token_expires_in":86400,
+                "scope":"foo"
+            }
+        "#.parse().unwrap();
+        let bearer = Bearer::<Refresh>::from_response(&json).unwrap();
+        assert!(bearer.extra().is_empty());
+    }
+
+    #[test]
+    fn serialize_extra() {
+        let json = r#"{"token_type":"Bearer","access_token":"aaaaaaaa","account_id":"dbid:foo"}"#
+            .parse()
+            .unwrap();
+        let token = Bearer::<Static>::from_response(&json).unwrap();
+        let serialized = serde_json::to_string(&token).unwrap();
+        let de: Bearer<Static> = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(Some("dbid:foo"), de.extra_str("account_id"));
+
+        let serialized = serde_json::to_string(&bearer()).unwrap();
+        assert!(!serialized.contains("extra"));
+        let de: Bearer<Static> = serde_json::from_str(
+            r#"{"access_token":"aaaaaaaa","scope":null,"lifetime":null}"#
+        ).unwrap();
+        assert_eq!(bearer(), de);
+    }
+
 }
@@ -0,0 +1,148 @@
+use serde::de::{Deserialize, Deserializer, Error as DeError};
+use serde::ser::{Serialize, Serializer};
+use serde_json::Value;
+
+use client::response::{expect_object, str_field, FromResponse, ParseError, ParsePolicy};
+use secret::Secret;
+use token::{Token, Lifetime, StorableLifetime, StoredToken, TokenType};
+
+/// The DPoP token type.
+///
+/// DPoP access tokens are bound to the client's `DPoPKey` and must be sent along with a proof.
+/// Clients created with `Client::with_dpop` add proofs to token requests, and `AuthorizedClient`
+/// adds them to resource requests.
+///
+/// DPoP tokens serialize as a `StoredToken`.
+///
+/// See [RFC 9449](https://www.rfc-editor.org/rfc/rfc9449).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DPoP<L: Lifetime> {
+    access_token: Secret,
+    scope: Option<String>,
+    lifetime: L,
+}
+
+impl<L: Lifetime> Token<L> for DPoP<L> {
+    fn access_token(&self) -> &str {
+        self.access_token.expose()
+    }
+    fn scope(&self) -> Option<&str> {
+        self.scope.as_ref().map(|s| &s[..])
+    }
+    fn lifetime(&self) -> &L {
+        &self.lifetime
+    }
+    fn token_type(&self) -> TokenType {
+        TokenType::DPoP
+    }
+}
+
+impl<L: Lifetime> DPoP<L> {
+    pub(crate) fn from_response_and_lifetime(
+        json: &Value,
+        lifetime: L,
+        policy: &ParsePolicy,
+    ) -> Result<Self, ParseError> {
+        let obj = expect_object(json)?;
+
+        policy.check_token_type(obj, "DPoP")?;
+
+        let access_token = str_field(obj, "access_token")?;
+        let scope = obj.get("scope").and_then(Value::as_str);
+
+        Ok(DPoP {
+            access_token: access_token.into(),
+            scope: scope.map(Into::into),
+            lifetime,
+        })
+    }
+}
+
+impl<L: StorableLifetime> DPoP<L> {
+    /// Converts the token to the versioned serialization format.
+    pub fn to_stored(&self) -> StoredToken {
+        StoredToken::new(
+            &TokenType::DPoP,
+            self.access_token.clone(),
+            self.scope.clone(),
+            self.lifetime.to_stored(),
+        )
+    }
+
+    /// Converts the token from the versioned serialization format.
+    pub fn from_stored(stored: StoredToken) -> Result<Self, ParseError> {
+        stored.expect_type(&TokenType::DPoP)?;
+        Ok(DPoP {
+            access_token: stored.access_token,
+            scope: stored.scope,
+            lifetime: L::from_stored(stored.lifetime)?,
+        })
+    }
+}
+
+impl<L: StorableLifetime> Serialize for DPoP<L> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_stored().serialize(serializer)
+    }
+}
+
+impl<'de, L: StorableLifetime> Deserialize<'de> for DPoP<L> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredToken::from_deserializer(deserializer, &TokenType::DPoP)?;
+        DPoP::from_stored(stored).map_err(D::Error::custom)
+    }
+}
+
+impl<L: Lifetime> FromResponse for DPoP<L> {
+    fn from_response(json: &Value) -> Result<Self, ParseError> {
+        DPoP::from_response_with(json, &ParsePolicy::default())
+    }
+
+    fn from_response_inherit(json: &Value, prev: &Self) -> Result<Self, ParseError> {
+        DPoP::from_response_inherit_with(json, prev, &ParsePolicy::default())
+    }
+
+    fn from_response_with(json: &Value, policy: &ParsePolicy) -> Result<Self, ParseError> {
+        let lifetime = FromResponse::from_response_with(json, policy)?;
+        DPoP::from_response_and_lifetime(json, lifetime, policy)
+    }
+
+    fn from_response_inherit_with(
+        json: &Value,
+        prev: &Self,
+        policy: &ParsePolicy,
+    ) -> Result<Self, ParseError> {
+        let lifetime = FromResponse::from_response_inherit_with(json, &prev.lifetime, policy)?;
+        DPoP::from_response_and_lifetime(json, lifetime, policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use client::response::{FromResponse, ParseError};
+    use secret::Secret;
+    use token::Static;
+    use super::DPoP;
+
+    #[test]
+    fn from_response() {
+        let json = r#"{"token_type":"DPoP","access_token":"aaaaaaaa"}"#.parse().unwrap();
+        assert_eq!(
+            DPoP {
+                access_token: Secret::from("aaaaaaaa"),
+                scope: None,
+                lifetime: Static,
+            },
+            DPoP::<Static>::from_response(&json).unwrap()
+        );
+    }
+
+    #[test]
+    fn from_response_with_bearer_token_type() {
+        let json = r#"{"token_type":"Bearer","access_token":"aaaaaaaa"}"#.parse().unwrap();
+        assert_eq!(
+            ParseError::expected_field_value("token_type", "DPoP"),
+            DPoP::<Static>::from_response(&json).unwrap_err()
+        );
+    }
+}
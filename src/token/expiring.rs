@@ -1,13 +1,15 @@
-use chrono::{DateTime, Utc, Duration};
+use chrono::{DateTime, Utc};
 use serde_json::Value;
 
-use client::response::{FromResponse, ParseError};
-use token::Lifetime;
+use client::response::{expect_object, FromResponse, ParseError, ParsePolicy};
+use token::{Lifetime, StorableLifetime, StoredLifetime};
 
 /// An expiring token.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Expiring {
     expires: DateTime<Utc>,
+    #[serde(default)]
+    issued: Option<DateTime<Utc>>,
 }
 
 impl Expiring {
@@ -17,22 +19,49 @@ impl Expiring {
 
 impl Lifetime for Expiring {
     fn expired(&self) -> bool { self.expires < Utc::now() }
+
+    fn issued_at(&self) -> Option<DateTime<Utc>> { self.issued }
+
+    fn expires_at(&self) -> Option<DateTime<Utc>> { Some(self.expires) }
+}
+
+impl StorableLifetime for Expiring {
+    fn to_stored(&self) -> StoredLifetime {
+        StoredLifetime {
+            expires: Some(self.expires),
+            issued: self.issued,
+            ..StoredLifetime::default()
+        }
+    }
+
+    fn from_stored(stored: StoredLifetime) -> Result<Self, ParseError> {
+        if stored.refresh_token.is_some() {
+            return Err(ParseError::unexpected_field("refresh_token"));
+        }
+        Ok(Expiring {
+            expires: stored.expires
+                .ok_or_else(|| ParseError::expected_field_type("expires", "date"))?,
+            issued: stored.issued,
+        })
+    }
 }
 
 impl FromResponse for Expiring {
     fn from_response(json: &Value) -> Result<Self, ParseError> {
-        let obj = json.as_object().ok_or(ParseError::ExpectedType("object"))?;
+        Expiring::from_response_with(json, &ParsePolicy::default())
+    }
 
-        if obj.contains_key("refresh_token") {
-            return Err(ParseError::UnexpectedField("refresh_token"));
-        }
+    fn from_response_with(json: &Value, policy: &ParsePolicy) -> Result<Self, ParseError> {
+        let obj = expect_object(json)?;
+
+        policy.check_unexpected(obj, "refresh_token")?;
 
-        let expires_in = obj.get("expires_in")
-            .and_then(Value::as_i64)
-            .ok_or(ParseError::ExpectedFieldType("expires_in", "i64"))?;
+        let (issued, expires) = policy.expiry(obj)?
+            .ok_or_else(|| ParseError::expected_field_type("expires_in", "i64"))?;
 
         Ok(Expiring {
-            expires: Utc::now() + Duration::seconds(expires_in),
+            expires,
+            issued: Some(issued),
         })
     }
 }
@@ -41,7 +70,10 @@ impl FromResponse for Expiring {
 mod tests {
     use chrono::{Utc, Duration};
 
-    use client::response::FromResponse;
+    use serde_json;
+
+    use client::response::{FromResponse, ParseError, ParsePolicy};
+    use token::Lifetime;
     use super::Expiring;
 
     #[test]
@@ -51,4 +83,89 @@ mod tests {
         assert!(expiring.expires > Utc::now());
         assert!(expiring.expires <= Utc::now() + Duration::seconds(3600));
     }
+
+    #[test]
+    fn from_response_with_refresh_token() {
+        let json = r#"{"expires_in":3600,"refresh_token":"aaaaaaaa"}"#.parse().unwrap();
+        assert_eq!(
+            ParseError::unexpected_field("refresh_token"),
+            Expiring::from_response(&json).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn from_response_lenient() {
+        let json = r#"{"expires_in":"3600"}"#.parse().unwrap();
+        assert_eq!(
+            ParseError::expected_field_type("expires_in", "i64"),
+            Expiring::from_response_with(&json, &ParsePolicy::strict()).unwrap_err()
+        );
+
+        let json = r#"{"expires_in":"3600","refresh_token":"aaaaaaaa"}"#.parse().unwrap();
+        let expiring = Expiring::from_response_with(&json, &ParsePolicy::lenient()).unwrap();
+        assert!(expiring.expires > Utc::now());
+        assert!(expiring.expires <= Utc::now() + Duration::seconds(3600));
+    }
+
+    #[test]
+    fn lifetime() {
+        let json = r#"{"expires_in":3600}"#.parse().unwrap();
+        let expiring = Expiring::from_response(&json).unwrap();
+        let issued = expiring.issued_at().unwrap();
+        assert!(issued <= Utc::now());
+        assert_eq!(Some(issued + Duration::seconds(3600)), expiring.expires_at());
+
+        let remaining = expiring.remaining().unwrap();
+        assert!(remaining > Duration::seconds(3590) && remaining <= Duration::seconds(3600));
+        assert!(expiring.elapsed_fraction().unwrap() < 0.01);
+    }
+
+    #[test]
+    fn lifetime_expired() {
+        let expiring = Expiring {
+            expires: Utc::now() - Duration::seconds(60),
+            issued: Some(Utc::now() - Duration::seconds(120)),
+        };
+        assert_eq!(Some(Duration::zero()), expiring.remaining());
+        assert_eq!(Some(1.0), expiring.elapsed_fraction());
+
+        let expiring = Expiring {
+            expires: Utc::now() + Duration::seconds(60),
+            issued: Some(Utc::now() - Duration::seconds(60)),
+        };
+        let fraction = expiring.elapsed_fraction().unwrap();
+        assert!(fraction > 0.49 && fraction < 0.51);
+    }
+
+    #[test]
+    fn lifetime_without_issued() {
+        let json = r#"{"expires":"2016-01-01T00:00:00Z"}"#;
+        let expiring: Expiring = serde_json::from_str(json).unwrap();
+        assert_eq!(None, expiring.issued_at());
+        assert_eq!(None, expiring.elapsed_fraction());
+        assert_eq!(Some(Duration::zero()), expiring.remaining());
+    }
+
+    #[test]
+    fn from_response_jwt_expiry() {
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        use base64::Engine;
+
+        let iat = Utc::now().timestamp() - 600;
+        let claims = format!(r#"{{"iat":{},"exp":{}}}"#, iat, iat + 3600);
+        let json = json!({
+            "access_token": format!("e30.{}.c2ln", URL_SAFE_NO_PAD.encode(claims)),
+        });
+        assert_eq!(
+            ParseError::expected_field_type("expires_in", "i64"),
+            Expiring::from_response(&json).unwrap_err()
+        );
+
+        let policy = ParsePolicy::strict().with_jwt_expiry(true);
+        let expiring = Expiring::from_response_with(&json, &policy).unwrap();
+        assert_eq!(iat, expiring.issued_at().unwrap().timestamp());
+        assert_eq!(iat + 3600, expiring.expires_at().unwrap().timestamp());
+        let fraction = expiring.elapsed_fraction().unwrap();
+        assert!(fraction > 0.16 && fraction < 0.17);
+    }
 }
@@ -0,0 +1,155 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use client::response::{expect_object, FromResponse, ParseError, ParsePolicy};
+use secret::Secret;
+use token::{Lifetime, Refreshable, StorableLifetime, StoredLifetime};
+
+/// A token which may expire and may be refreshed, as decided by the token response.
+///
+/// Useful with providers which only sometimes include `expires_in` or `refresh_token`, such as
+/// Google, which issues refresh tokens only on first consent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Flexible {
+    refresh_token: Option<Secret>,
+    expires: Option<DateTime<Utc>>,
+    #[serde(default)]
+    issued: Option<DateTime<Utc>>,
+}
+
+impl Flexible {
+    /// Returns the refresh token, if one was issued.
+    ///
+    /// See [RFC 6749, section 1.5](http://tools.ietf.org/html/rfc6749#section-1.5).
+    pub fn refresh_token(&self) -> Option<&str> { self.refresh_token.as_ref().map(Secret::expose) }
+
+    /// Returns the expiry time of the access token, if known.
+    pub fn expires(&self) -> Option<&DateTime<Utc>> { self.expires.as_ref() }
+}
+
+impl Lifetime for Flexible {
+    fn expired(&self) -> bool { self.expires.is_some_and(|expires| expires < Utc::now()) }
+
+    fn issued_at(&self) -> Option<DateTime<Utc>> { self.issued }
+
+    fn expires_at(&self) -> Option<DateTime<Utc>> { self.expires }
+}
+
+impl Refreshable for Flexible {
+    fn refresh_token(&self) -> Option<&str> { Flexible::refresh_token(self) }
+}
+
+impl StorableLifetime for Flexible {
+    fn to_stored(&self) -> StoredLifetime {
+        StoredLifetime {
+            refresh_token: self.refresh_token.clone(),
+            expires: self.expires,
+            issued: self.issued,
+            ..StoredLifetime::default()
+        }
+    }
+
+    fn from_stored(stored: StoredLifetime) -> Result<Self, ParseError> {
+        Ok(Flexible {
+            refresh_token: stored.refresh_token,
+            expires: stored.expires,
+            issued: stored.issued,
+        })
+    }
+}
+
+impl FromResponse for Flexible {
+    fn from_response(json: &Value) -> Result<Self, ParseError> {
+        Flexible::from_response_with(json, &ParsePolicy::default())
+    }
+
+    fn from_response_inherit(json: &Value, prev: &Self) -> Result<Self, ParseError> {
+        Flexible::from_response_inherit_with(json, prev, &ParsePolicy::default())
+    }
+
+    fn from_response_with(json: &Value, policy: &ParsePolicy) -> Result<Self, ParseError> {
+        let obj = expect_object(json)?;
+
+        let refresh_token = match obj.get("refresh_token") {
+            None => None,
+            Some(value) => Some(
+                value.as_str().ok_or_else(|| {
+                    ParseError::expected_field_type("refresh_token", "string").with_value(value)
+                })?
+            ),
+        };
+        let expiry = policy.expiry(obj)?;
+
+        Ok(Flexible {
+            refresh_token: refresh_token.map(Into::into),
+            expires: expiry.map(|(_, expires)| expires),
+            issued: Some(expiry.map_or_else(Utc::now, |(issued, _)| issued)),
+        })
+    }
+
+    /// The refresh token is inherited if the response does not include a new one. The expiry time
+    /// is not, since it refers to the previous access token.
+    fn from_response_inherit_with(
+        json: &Value,
+        prev: &Self,
+        policy: &ParsePolicy,
+    ) -> Result<Self, ParseError> {
+        let mut flexible = Flexible::from_response_with(json, policy)?;
+        if flexible.refresh_token.is_none() {
+            flexible.refresh_token = prev.refresh_token.clone();
+        }
+        Ok(flexible)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Utc, Duration};
+
+    use client::response::{FromResponse, ParseError};
+    use token::Lifetime;
+    use super::Flexible;
+
+    #[test]
+    fn from_response() {
+        let json = r#"{"refresh_token":"aaaaaaaa","expires_in":3600}"#.parse().unwrap();
+        let flexible = Flexible::from_response(&json).unwrap();
+        assert_eq!(Some("aaaaaaaa"), flexible.refresh_token());
+        assert!(*flexible.expires().unwrap() > Utc::now());
+        assert!(*flexible.expires().unwrap() <= Utc::now() + Duration::seconds(3600));
+        assert!(!flexible.expired());
+    }
+
+    #[test]
+    fn from_response_without_fields() {
+        let flexible = Flexible::from_response(&"{}".parse().unwrap()).unwrap();
+        assert_eq!(None, flexible.refresh_token());
+        assert_eq!(None, flexible.expires());
+        assert!(!flexible.expired());
+    }
+
+    #[test]
+    fn from_response_with_invalid_refresh_token() {
+        let json = r#"{"refresh_token":7}"#.parse().unwrap();
+        assert_eq!(
+            ParseError::expected_field_type("refresh_token", "string"),
+            Flexible::from_response(&json).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn from_response_inherit() {
+        let json = r#"{"refresh_token":"aaaaaaaa","expires_in":0}"#.parse().unwrap();
+        let prev = Flexible::from_response(&json).unwrap();
+
+        let json = r#"{"expires_in":3600}"#.parse().unwrap();
+        let flexible = Flexible::from_response_inherit(&json, &prev).unwrap();
+        assert_eq!(Some("aaaaaaaa"), flexible.refresh_token());
+        assert!(!flexible.expired());
+
+        let json = r#"{"refresh_token":"bbbbbbbb"}"#.parse().unwrap();
+        let flexible = Flexible::from_response_inherit(&json, &prev).unwrap();
+        assert_eq!(Some("bbbbbbbb"), flexible.refresh_token());
+        assert_eq!(None, flexible.expires());
+    }
+}
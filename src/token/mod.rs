@@ -1,19 +1,33 @@
 //! Tokens.
 //!
 //! Access token types are abstracted through the `Token` trait. See
-//! [RFC 6749, section 7.1](http://tools.ietf.org/html/rfc6749#section-7.1).
+//! [RFC 6749, section 7.1](http://tools.ietf.org/html/rfc6749#section-7.1). Providers issuing
+//! several token types can use `AnyToken`, which dispatches on the `token_type` of the response.
 //!
-//! Expiring and non-expiring tokens are abstracted through the `Lifetime` trait.
+//! Expiring and non-expiring tokens are abstracted through the `Lifetime` trait, and lifetimes of
+//! tokens which may be refreshed through the `Refreshable` trait.
 
+mod any;
 mod bearer;
+mod dpop;
 mod expiring;
+mod flexible;
 mod refresh;
 mod statik;
+mod stored;
+mod token_type;
 
-pub use self::bearer::Bearer;
+pub use self::any::AnyToken;
+pub use self::bearer::{Bearer, Transmission};
+pub use self::dpop::DPoP;
 pub use self::expiring::Expiring;
+pub use self::flexible::Flexible;
 pub use self::refresh::Refresh;
 pub use self::statik::Static;
+pub use self::stored::{StorableLifetime, StoredLifetime, StoredToken};
+pub use self::token_type::TokenType;
+
+use chrono::{DateTime, Duration, Utc};
 
 use client::response::FromResponse;
 
@@ -31,10 +45,63 @@ pub trait Token<L: Lifetime>: FromResponse {
 
     /// Returns the token lifetime.
     fn lifetime(&self) -> &L;
+
+    /// Returns the token type.
+    ///
+    /// See [RFC 6749, section 7.1](http://tools.ietf.org/html/rfc6749#section-7.1).
+    fn token_type(&self) -> TokenType;
+
+    /// Returns the value of the `Authorization` header presenting the token in resource requests.
+    ///
+    /// Returns `None` for token types which cannot be presented in the header alone. DPoP tokens
+    /// must also be sent with a proof.
+    fn authorization_header(&self) -> Option<String> {
+        match self.token_type() {
+            TokenType::Bearer | TokenType::DPoP =>
+                Some(format!("{} {}", self.token_type(), self.access_token())),
+            _ => None,
+        }
+    }
 }
 
 /// OAuth 2.0 token lifetimes.
 pub trait Lifetime: FromResponse {
     /// Returns true if the access token is no longer valid.
     fn expired(&self) -> bool;
+
+    /// Returns the time the access token was issued, if known.
+    ///
+    /// Tokens deserialized from versions which did not record it return `None`.
+    fn issued_at(&self) -> Option<DateTime<Utc>> { None }
+
+    /// Returns the expiry time of the access token, if it expires.
+    fn expires_at(&self) -> Option<DateTime<Utc>> { None }
+
+    /// Returns the time remaining until the access token expires, if it expires.
+    ///
+    /// Returns zero once the access token has expired.
+    fn remaining(&self) -> Option<Duration> {
+        self.expires_at().map(|expires| (expires - Utc::now()).max(Duration::zero()))
+    }
+
+    /// Returns the fraction of the access token's lifetime which has elapsed, between 0 and 1.
+    ///
+    /// Returns `None` unless both the issue and expiry times are known.
+    fn elapsed_fraction(&self) -> Option<f64> {
+        let issued = self.issued_at()?;
+        let lifetime = (self.expires_at()? - issued).num_milliseconds();
+        if lifetime <= 0 {
+            return Some(1.0);
+        }
+        let elapsed = (Utc::now() - issued).num_milliseconds() as f64 / lifetime as f64;
+        Some(elapsed.clamp(0.0, 1.0))
+    }
+}
+
+/// Lifetimes of tokens which may be refreshed.
+///
+/// See [RFC 6749, section 6](http://tools.ietf.org/html/rfc6749#section-6).
+pub trait Refreshable: Lifetime {
+    /// Returns the refresh token, if one was issued.
+    fn refresh_token(&self) -> Option<&str>;
 }
@@ -1,62 +1,135 @@
 use chrono::{DateTime, Utc, Duration};
 use serde_json::Value;
 
-use client::response::{FromResponse, ParseError};
-use token::Lifetime;
+use client::response::{expect_object, str_field, FromResponse, ParseError, ParsePolicy};
+use secret::Secret;
+use token::{Lifetime, Refreshable, StorableLifetime, StoredLifetime};
 
 /// An expiring token which can be refreshed.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Refresh {
-    refresh_token: String,
+    refresh_token: Secret,
     expires: DateTime<Utc>,
+    #[serde(default)]
+    issued: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refresh_token_expires: Option<DateTime<Utc>>,
 }
 
 impl Refresh {
     /// Returns the refresh token.
     ///
     /// See [RFC 6749, section 1.5](http://tools.ietf.org/html/rfc6749#section-1.5).
-    pub fn refresh_token(&self) -> &str { &self.refresh_token }
+    pub fn refresh_token(&self) -> &str { self.refresh_token.expose() }
 
     /// Returns the expiry time of the access token.
     pub fn expires(&self) -> &DateTime<Utc> { &self.expires }
+
+    /// Returns the expiry time of the refresh token, if the provider sent
+    /// `refresh_token_expires_in`.
+    pub fn refresh_token_expires(&self) -> Option<&DateTime<Utc>> {
+        self.refresh_token_expires.as_ref()
+    }
+
+    /// Returns true if the refresh token is known to have expired.
+    pub fn refresh_token_expired(&self) -> bool {
+        self.reauthorization_needed_within(Duration::zero())
+    }
+
+    /// Returns true if the refresh token is known to expire within a duration, after which the
+    /// user must authorize the client again.
+    pub fn reauthorization_needed_within(&self, within: Duration) -> bool {
+        self.refresh_token_expires.is_some_and(|expires| expires - within <= Utc::now())
+    }
 }
 
 impl Lifetime for Refresh {
     fn expired(&self) -> bool { self.expires < Utc::now() }
+
+    fn issued_at(&self) -> Option<DateTime<Utc>> { self.issued }
+
+    fn expires_at(&self) -> Option<DateTime<Utc>> { Some(self.expires) }
+}
+
+impl Refreshable for Refresh {
+    fn refresh_token(&self) -> Option<&str> { Some(self.refresh_token.expose()) }
+}
+
+impl StorableLifetime for Refresh {
+    fn to_stored(&self) -> StoredLifetime {
+        StoredLifetime {
+            refresh_token: Some(self.refresh_token.clone()),
+            expires: Some(self.expires),
+            issued: self.issued,
+            refresh_token_expires: self.refresh_token_expires,
+        }
+    }
+
+    fn from_stored(stored: StoredLifetime) -> Result<Self, ParseError> {
+        Ok(Refresh {
+            refresh_token: stored.refresh_token
+                .ok_or_else(|| ParseError::expected_field_type("refresh_token", "string"))?,
+            expires: stored.expires
+                .ok_or_else(|| ParseError::expected_field_type("expires", "date"))?,
+            issued: stored.issued,
+            refresh_token_expires: stored.refresh_token_expires,
+        })
+    }
 }
 
 impl FromResponse for Refresh {
     fn from_response(json: &Value) -> Result<Self, ParseError> {
-        let obj = json.as_object().ok_or(ParseError::ExpectedType("object"))?;
+        Refresh::from_response_with(json, &ParsePolicy::default())
+    }
 
-        let refresh_token = obj.get("refresh_token")
-            .and_then(Value::as_str)
-            .ok_or(ParseError::ExpectedFieldType("refresh_token", "string"))?;
-        let expires_in = obj.get("expires_in")
-            .and_then(Value::as_i64)
-            .ok_or(ParseError::ExpectedFieldType("expires_in", "i64"))?;
+    fn from_response_inherit(json: &Value, prev: &Self) -> Result<Self, ParseError> {
+        Refresh::from_response_inherit_with(json, prev, &ParsePolicy::default())
+    }
+
+    fn from_response_with(json: &Value, policy: &ParsePolicy) -> Result<Self, ParseError> {
+        let obj = expect_object(json)?;
+
+        let refresh_token = str_field(obj, "refresh_token")?;
+        let (issued, expires) = policy.expiry(obj)?
+            .ok_or_else(|| ParseError::expected_field_type("expires_in", "i64"))?;
+        let refresh_token_expires_in = policy.i64_field(obj, "refresh_token_expires_in")?;
 
         Ok(Refresh {
             refresh_token: refresh_token.into(),
-            expires: Utc::now() + Duration::seconds(expires_in),
+            expires,
+            issued: Some(issued),
+            refresh_token_expires: refresh_token_expires_in
+                .map(|expires_in| Utc::now() + Duration::seconds(expires_in)),
         })
     }
 
-    fn from_response_inherit(json: &Value, prev: &Self) -> Result<Self, ParseError> {
-        let obj = json.as_object().ok_or(ParseError::ExpectedType("object"))?;
+    /// The refresh token is inherited if the response does not include a new one. Its expiry time
+    /// is inherited along with it, unless the response includes a new one; a rotated refresh token
+    /// without `refresh_token_expires_in` has no known expiry time.
+    fn from_response_inherit_with(
+        json: &Value,
+        prev: &Self,
+        policy: &ParsePolicy,
+    ) -> Result<Self, ParseError> {
+        let obj = expect_object(json)?;
 
         let refresh_token = obj.get("refresh_token")
             .and_then(Value::as_str)
-            .or(Some(&prev.refresh_token))
-            .ok_or(ParseError::ExpectedFieldType("refresh_token", "string"))?;
+            .or(Some(prev.refresh_token.expose()))
+            .ok_or_else(|| ParseError::expected_field_type("refresh_token", "string"))?;
 
-        let expires_in = obj.get("expires_in")
-            .and_then(Value::as_i64)
-            .ok_or(ParseError::ExpectedFieldType("expires_in", "i64"))?;
+        let (issued, expires) = policy.expiry(obj)?
+            .ok_or_else(|| ParseError::expected_field_type("expires_in", "i64"))?;
+        let inherited = refresh_token == prev.refresh_token.expose();
+        let refresh_token_expires = policy.i64_field(obj, "refresh_token_expires_in")?
+            .map(|expires_in| Utc::now() + Duration::seconds(expires_in))
+            .or(if inherited { prev.refresh_token_expires } else { None });
 
         Ok(Refresh {
             refresh_token: refresh_token.into(),
-            expires: Utc::now() + Duration::seconds(expires_in),
+            expires,
+            issued: Some(issued),
+            refresh_token_expires,
         })
     }
 }
@@ -65,14 +138,17 @@ impl FromResponse for Refresh {
 mod tests {
     use chrono::{Utc, Duration};
 
+    use serde_json;
+
     use client::response::FromResponse;
+    use secret::Secret;
     use super::Refresh;
 
     #[test]
     fn from_response() {
         let json = r#"{"refresh_token":"aaaaaaaa","expires_in":3600}"#.parse().unwrap();
         let refresh = Refresh::from_response(&json).unwrap();
-        assert_eq!("aaaaaaaa", refresh.refresh_token);
+        assert_eq!("aaaaaaaa", refresh.refresh_token());
         assert!(refresh.expires > Utc::now());
         assert!(refresh.expires <= Utc::now() + Duration::seconds(3600));
     }
@@ -81,12 +157,66 @@ mod tests {
     fn from_response_inherit() {
         let json = r#"{"expires_in":3600}"#.parse().unwrap();
         let prev = Refresh {
-            refresh_token: String::from("aaaaaaaa"),
+            refresh_token: Secret::from("aaaaaaaa"),
             expires: Utc::now(),
+            issued: None,
+            refresh_token_expires: None,
         };
         let refresh = Refresh::from_response_inherit(&json, &prev).unwrap();
-        assert_eq!("aaaaaaaa", refresh.refresh_token);
+        assert_eq!("aaaaaaaa", refresh.refresh_token());
         assert!(refresh.expires > Utc::now());
         assert!(refresh.expires <= Utc::now() + Duration::seconds(3600));
     }
+
+    #[test]
+    fn from_response_refresh_token_expires_in() {
+        let json = r#"
+            {"refresh_token":"aaaaaaaa","expires_in":3600,"refresh_token_expires_in":86400}
+        "#.parse().unwrap();
+        let refresh = Refresh::from_response(&json).unwrap();
+        let expires = *refresh.refresh_token_expires().unwrap();
+        assert!(expires > Utc::now() + Duration::seconds(86300));
+        assert!(expires <= Utc::now() + Duration::seconds(86400));
+        assert!(!refresh.refresh_token_expired());
+        assert!(!refresh.reauthorization_needed_within(Duration::hours(1)));
+        assert!(refresh.reauthorization_needed_within(Duration::days(2)));
+
+        let json = r#"{"refresh_token":"aaaaaaaa","expires_in":3600}"#.parse().unwrap();
+        let refresh = Refresh::from_response(&json).unwrap();
+        assert_eq!(None, refresh.refresh_token_expires());
+        assert!(!refresh.reauthorization_needed_within(Duration::days(365)));
+    }
+
+    #[test]
+    fn from_response_inherit_refresh_token_expires() {
+        let expires = Utc::now() + Duration::days(1);
+        let prev = Refresh {
+            refresh_token: Secret::from("aaaaaaaa"),
+            expires: Utc::now(),
+            issued: None,
+            refresh_token_expires: Some(expires),
+        };
+
+        let json = r#"{"expires_in":3600}"#.parse().unwrap();
+        let refresh = Refresh::from_response_inherit(&json, &prev).unwrap();
+        assert_eq!(Some(&expires), refresh.refresh_token_expires());
+
+        let json = r#"
+            {"refresh_token":"bbbbbbbb","expires_in":3600,"refresh_token_expires_in":0}
+        "#.parse().unwrap();
+        let refresh = Refresh::from_response_inherit(&json, &prev).unwrap();
+        assert!(refresh.refresh_token_expired());
+
+        let json = r#"{"refresh_token":"bbbbbbbb","expires_in":3600}"#.parse().unwrap();
+        let refresh = Refresh::from_response_inherit(&json, &prev).unwrap();
+        assert_eq!(None, refresh.refresh_token_expires());
+    }
+
+    #[test]
+    fn deserialize_without_refresh_token_expires() {
+        let json = r#"{"refresh_token":"aaaaaaaa","expires":"2016-01-01T00:00:00Z"}"#;
+        let refresh: Refresh = serde_json::from_str(json).unwrap();
+        assert_eq!(None, refresh.refresh_token_expires());
+        assert!(!serde_json::to_string(&refresh).unwrap().contains("refresh_token_expires"));
+    }
 }
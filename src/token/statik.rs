@@ -1,7 +1,7 @@
 use serde_json::Value;
 
-use client::response::{FromResponse, ParseError};
-use token::Lifetime;
+use client::response::{expect_object, FromResponse, ParseError, ParsePolicy};
+use token::{Lifetime, StorableLifetime, StoredLifetime};
 
 /// A static, non-expiring token.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -11,19 +11,33 @@ impl Lifetime for Static {
     fn expired(&self) -> bool { false }
 }
 
+impl StorableLifetime for Static {
+    fn to_stored(&self) -> StoredLifetime { StoredLifetime::default() }
+
+    fn from_stored(stored: StoredLifetime) -> Result<Self, ParseError> {
+        if stored.expires.is_some() {
+            return Err(ParseError::unexpected_field("expires"));
+        }
+        Ok(Static)
+    }
+}
+
 impl FromResponse for Static {
     fn from_response(json: &Value) -> Result<Self, ParseError> {
-        let obj = json.as_object().ok_or(ParseError::ExpectedType("object"))?;
-        if obj.contains_key("expires_in") {
-            return Err(ParseError::UnexpectedField("expires_in"));
-        }
+        Static::from_response_with(json, &ParsePolicy::default())
+    }
+
+    fn from_response_with(json: &Value, policy: &ParsePolicy) -> Result<Self, ParseError> {
+        let obj = expect_object(json)?;
+        policy.check_unexpected(obj, "expires_in")?;
         Ok(Static)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use client::response::{FromResponse, ParseError};
+    use client::response::{FromResponse, ParseError, ParsePolicy};
+    use token::Lifetime;
     use super::Static;
 
     #[test]
@@ -36,8 +50,22 @@ mod tests {
     fn from_response_with_expires_in() {
         let json = r#"{"expires_in":3600}"#.parse().unwrap();
         assert_eq!(
-            ParseError::UnexpectedField("expires_in"),
+            ParseError::unexpected_field("expires_in"),
             Static::from_response(&json).unwrap_err()
         );
     }
+
+    #[test]
+    fn from_response_lenient_with_expires_in() {
+        let json = r#"{"expires_in":3600}"#.parse().unwrap();
+        assert_eq!(Static, Static::from_response_with(&json, &ParsePolicy::lenient()).unwrap());
+    }
+
+    #[test]
+    fn lifetime() {
+        assert_eq!(None, Static.issued_at());
+        assert_eq!(None, Static.expires_at());
+        assert_eq!(None, Static.remaining());
+        assert_eq!(None, Static.elapsed_fraction());
+    }
 }
@@ -0,0 +1,282 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::de::{Deserializer, Error as DeError};
+use serde::Deserialize;
+use serde_json::{self, Map, Value};
+
+use client::response::{str_field, ParseError};
+use secret::{RedactedFields, Secret};
+use token::{Lifetime, TokenType};
+
+/// A token in the versioned serialization format.
+///
+/// Built-in token types serialize through this format, so they can be deserialized into any token
+/// of the same type whose lifetime can hold the stored fields. For example, a `Bearer<Expiring>`
+/// can be deserialized as a `Bearer<Flexible>`.
+///
+/// Tokens serialized by 0.16, which had no version, are migrated when deserialized. Unknown fields
+/// are ignored, so fields may be added without breaking older readers. `version` is only
+/// increased for changes which older readers cannot interpret, and such tokens are rejected.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredToken {
+    /// The format version.
+    pub version: u32,
+
+    /// The token type.
+    pub token_type: String,
+
+    /// The access token.
+    pub access_token: Secret,
+
+    /// The scope, if available.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+
+    /// The token lifetime.
+    #[serde(default)]
+    pub lifetime: StoredLifetime,
+
+    /// Additional fields of the token response.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+}
+
+impl fmt::Debug for StoredToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("StoredToken")
+            .field("version", &self.version)
+            .field("token_type", &self.token_type)
+            .field("access_token", &self.access_token)
+            .field("scope", &self.scope)
+            .field("lifetime", &self.lifetime)
+            .field("extra", &RedactedFields(&self.extra))
+            .finish()
+    }
+}
+
+/// The lifetime of a token in the versioned serialization format.
+///
+/// Holds the fields of every lifetime type, so tokens can be converted between them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredLifetime {
+    /// The refresh token, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<Secret>,
+
+    /// The expiry time of the access token, if it expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+
+    /// The time the access token was issued, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issued: Option<DateTime<Utc>>,
+
+    /// The expiry time of the refresh token, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token_expires: Option<DateTime<Utc>>,
+}
+
+/// Token lifetimes which can be serialized in the versioned format.
+pub trait StorableLifetime: Lifetime + Sized {
+    /// Converts the lifetime to the stored format.
+    fn to_stored(&self) -> StoredLifetime;
+
+    /// Converts the lifetime from the stored format.
+    ///
+    /// Fails if the stored lifetime lacks fields this lifetime requires.
+    fn from_stored(stored: StoredLifetime) -> Result<Self, ParseError>;
+}
+
+impl StoredToken {
+    /// The current format version.
+    pub const VERSION: u32 = 1;
+
+    /// Creates a stored token of the current version.
+    pub fn new(
+        token_type: &TokenType,
+        access_token: Secret,
+        scope: Option<String>,
+        lifetime: StoredLifetime,
+    ) -> Self {
+        StoredToken {
+            version: StoredToken::VERSION,
+            token_type: token_type.as_str().to_owned(),
+            access_token,
+            scope,
+            lifetime,
+            extra: Map::new(),
+        }
+    }
+
+    /// Parses a serialized token, migrating it from earlier formats.
+    ///
+    /// Tokens serialized by 0.16 did not record their type, so they are assumed to be of
+    /// `token_type`.
+    pub fn migrate(json: Value, token_type: &TokenType) -> Result<Self, ParseError> {
+        let mut obj = match json {
+            Value::Object(obj) => obj,
+            json => return Err(ParseError::expected_type("object").with_value(&json)),
+        };
+
+        match obj.get("version").map(|version| (version, version.as_u64())) {
+            None => {},
+            Some((_, Some(version))) if version <= u64::from(StoredToken::VERSION) => {
+                return serde_json::from_value(Value::Object(obj))
+                    .map_err(|err| ParseError::expected_type("stored token").with_source(err));
+            },
+            Some((version, Some(_))) => return Err(
+                ParseError::expected_field_value(
+                    "version",
+                    format!("at most {}", StoredToken::VERSION),
+                ).with_value(version)
+            ),
+            Some((version, None)) =>
+                return Err(ParseError::expected_field_type("version", "u32").with_value(version)),
+        }
+
+        // 0.16: {"access_token": ..., "scope": ..., "lifetime": ...}
+        let access_token = Secret::from(str_field(&obj, "access_token")?);
+        let scope = match obj.remove("scope") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(s)) => Some(s),
+            Some(scope) =>
+                return Err(ParseError::expected_field_type("scope", "string").with_value(&scope)),
+        };
+        let lifetime = match obj.remove("lifetime") {
+            None | Some(Value::Null) => StoredLifetime::default(),
+            Some(lifetime) => serde_json::from_value(lifetime)
+                .map_err(|err| {
+                    ParseError::expected_field_type("lifetime", "object").with_source(err)
+                })?,
+        };
+        Ok(StoredToken::new(token_type, access_token, scope, lifetime))
+    }
+
+    /// Returns the token type.
+    pub fn token_type(&self) -> TokenType {
+        TokenType::from(&self.token_type[..])
+    }
+
+    pub(crate) fn expect_type(&self, expected: &TokenType) -> Result<(), ParseError> {
+        if self.token_type() == *expected {
+            Ok(())
+        } else {
+            Err(ParseError::expected_field_value("token_type", expected.as_str().to_owned())
+                .with_value(&Value::from(&self.token_type[..])))
+        }
+    }
+
+    /// Deserializes a token, migrating it from earlier formats.
+    pub(crate) fn from_deserializer<'de, D>(
+        deserializer: D,
+        token_type: &TokenType,
+    ) -> Result<Self, D::Error>
+    where D: Deserializer<'de> {
+        let json = Value::deserialize(deserializer)?;
+        StoredToken::migrate(json, token_type).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json;
+
+    use client::response::{FromResponse, ParseError};
+    use secret::Secret;
+    use token::{Bearer, Expiring, Flexible, Lifetime, Refresh, Static, Token, TokenType};
+    use super::{StoredLifetime, StoredToken};
+
+    #[test]
+    fn migrate_0_16_bearer_expiring() {
+        let json = r#"
+            {
+                "access_token": "aaaaaaaa",
+                "scope": "read",
+                "lifetime": {"expires": "2030-01-01T00:00:00Z"}
+            }
+        "#.parse().unwrap();
+        let stored = StoredToken::migrate(json, &TokenType::Bearer).unwrap();
+        assert_eq!(1, stored.version);
+        assert_eq!(TokenType::Bearer, stored.token_type());
+        assert_eq!("aaaaaaaa", stored.access_token.expose());
+        assert_eq!(Some("read"), stored.scope.as_ref().map(|s| &s[..]));
+        assert_eq!(Some(Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap()), stored.lifetime.expires);
+        assert_eq!(None, stored.lifetime.refresh_token);
+    }
+
+    #[test]
+    fn migrate_0_16_static() {
+        let json = r#"{"access_token":"aaaaaaaa","scope":null,"lifetime":null}"#.parse().unwrap();
+        let stored = StoredToken::migrate(json, &TokenType::Bearer).unwrap();
+        assert_eq!(StoredLifetime::default(), stored.lifetime);
+    }
+
+    #[test]
+    fn migrate_current() {
+        let json = r#"
+            {
+                "version": 1,
+                "token_type": "DPoP",
+                "access_token": "aaaaaaaa",
+                "lifetime": {},
+                "added_later": true
+            }
+        "#.parse().unwrap();
+        let stored = StoredToken::migrate(json, &TokenType::Bearer).unwrap();
+        assert_eq!(TokenType::DPoP, stored.token_type());
+    }
+
+    #[test]
+    fn migrate_newer_version() {
+        let json = r#"{"version":2,"token_type":"Bearer","access_token":"aaaaaaaa"}"#
+            .parse()
+            .unwrap();
+        assert_eq!(
+            Err(ParseError::expected_field_value("version", "at most 1")),
+            StoredToken::migrate(json, &TokenType::Bearer)
+        );
+    }
+
+    #[test]
+    fn serialize_versioned() {
+        let json = r#"
+            {"token_type":"Bearer","access_token":"aaaaaaaa","expires_in":3600,"refresh_token":"bbbbbbbb"}
+        "#.parse().unwrap();
+        let token = Bearer::<Refresh>::from_response(&json).unwrap();
+        let serialized = serde_json::to_value(&token).unwrap();
+        assert_eq!(json!(1), serialized["version"]);
+        assert_eq!(json!("Bearer"), serialized["token_type"]);
+        assert_eq!(json!("bbbbbbbb"), serialized["lifetime"]["refresh_token"]);
+
+        let de: Bearer<Refresh> = serde_json::from_value(serialized).unwrap();
+        assert_eq!(token, de);
+    }
+
+    #[test]
+    fn import_into_richer_lifetime() {
+        let saved = r#"{"access_token":"aaaaaaaa","scope":null,"lifetime":{"expires":"2030-01-01T00:00:00Z"}}"#;
+
+        let expiring: Bearer<Expiring> = serde_json::from_str(saved).unwrap();
+        let flexible: Bearer<Flexible> = serde_json::from_str(saved).unwrap();
+        assert_eq!("aaaaaaaa", flexible.access_token());
+        assert_eq!(expiring.lifetime().expires_at(), flexible.lifetime().expires_at());
+        assert_eq!(None, flexible.lifetime().refresh_token());
+
+        assert!(serde_json::from_str::<Bearer<Refresh>>(saved).is_err());
+        assert!(serde_json::from_str::<Bearer<Static>>(saved).is_err());
+    }
+
+    #[test]
+    fn wrong_token_type() {
+        let stored = StoredToken::new(
+            &TokenType::DPoP,
+            Secret::from("aaaaaaaa"),
+            None,
+            StoredLifetime::default(),
+        );
+        let json = serde_json::to_string(&stored).unwrap();
+        assert!(serde_json::from_str::<Bearer<Static>>(&json).is_err());
+    }
+}
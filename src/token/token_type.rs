@@ -0,0 +1,87 @@
+use std::fmt;
+
+/// Access token types.
+///
+/// See [RFC 6749, section 7.1](http://tools.ietf.org/html/rfc6749#section-7.1) and the
+/// [OAuth Access Token Types registry](https://www.iana.org/assignments/oauth-parameters/oauth-parameters.xhtml#token-types).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TokenType {
+    /// `Bearer`
+    ///
+    /// See [RFC 6750](http://tools.ietf.org/html/rfc6750).
+    Bearer,
+
+    /// `DPoP`
+    ///
+    /// See [RFC 9449](https://www.rfc-editor.org/rfc/rfc9449).
+    DPoP,
+
+    /// `mac`
+    ///
+    /// See [draft-ietf-oauth-v2-http-mac](https://tools.ietf.org/html/draft-ietf-oauth-v2-http-mac).
+    Mac,
+
+    /// `N_A`, for issued tokens which are not access tokens.
+    ///
+    /// See [RFC 8693, section 2.2.1](https://www.rfc-editor.org/rfc/rfc8693#section-2.2.1).
+    NotApplicable,
+
+    /// An unregistered token type.
+    Unrecognized(String),
+}
+
+impl TokenType {
+    /// Returns the registered name of the token type.
+    pub fn as_str(&self) -> &str {
+        match *self {
+            TokenType::Bearer => "Bearer",
+            TokenType::DPoP => "DPoP",
+            TokenType::Mac => "mac",
+            TokenType::NotApplicable => "N_A",
+            TokenType::Unrecognized(ref s) => s,
+        }
+    }
+}
+
+impl From<&str> for TokenType {
+    /// Token type names are case-insensitive.
+    fn from(s: &str) -> TokenType {
+        let registered = [
+            TokenType::Bearer,
+            TokenType::DPoP,
+            TokenType::Mac,
+            TokenType::NotApplicable,
+        ];
+        registered.iter()
+            .find(|token_type| token_type.as_str().eq_ignore_ascii_case(s))
+            .cloned()
+            .unwrap_or_else(|| TokenType::Unrecognized(s.to_owned()))
+    }
+}
+
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenType;
+
+    #[test]
+    fn from_str() {
+        assert_eq!(TokenType::Bearer, TokenType::from("Bearer"));
+        assert_eq!(TokenType::Bearer, TokenType::from("bearer"));
+        assert_eq!(TokenType::DPoP, TokenType::from("dpop"));
+        assert_eq!(TokenType::Mac, TokenType::from("MAC"));
+        assert_eq!(TokenType::NotApplicable, TokenType::from("N_A"));
+        assert_eq!(TokenType::Unrecognized(String::from("PoP")), TokenType::from("PoP"));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!("N_A", TokenType::NotApplicable.to_string());
+        assert_eq!("PoP", TokenType::Unrecognized(String::from("PoP")).to_string());
+    }
+}